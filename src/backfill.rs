@@ -0,0 +1,97 @@
+//!
+//! 历史提交记录补录
+//!
+//! 按版本号顺序将指定范围内的提交放入本地服务的提交队列，由服务同步到Worktile，
+//! 每放入一个版本保存一次进度，中断后重新执行相同的命令即可继续
+//!
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use std::{
+    fs, io::{self, Write}, path::PathBuf
+};
+
+/// 补录进度，按仓库和起始版本号区分
+/// 不记录结束版本号，未指定`--to`时最新版本随新的提交变化，仍然可以继续
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    from: u64,
+    /// 最后一个已放入队列的版本号
+    last: u64
+}
+
+/// 补录指定范围的提交记录
+/// `to`未指定时为仓库的最新版本，`force`为真时重新同步已同步过的版本
+///
+/// 按顺序放入本地服务的提交队列，由服务同步并记录台账，与钩子提交的版本不会重复同步
+pub fn run(
+    repo_path: &str,
+    repo_name: &str,
+    from: u64,
+    to: Option<u64>,
+    force: bool
+) -> Result<(), AnyError> {
+    let cfg = settings::SharedConfig::load()?;
+    let cp_path = PathBuf::from(cfg.config_string("backfill.path")).join(format!("{}.json", repo_name));
+
+    let to = match to {
+        Some(to) => to,
+        None => {
+            let repo = svn::open(&cfg, repo_path)?;
+            let mut system = actix_rt::System::new("backfill");
+            system.block_on(async move { repo.youngest().await })?
+        }
+    };
+    if from > to {
+        return Err(format!("起始版本[{}]大于结束版本[{}]", from, to).into());
+    }
+
+    //读取上次中断的进度
+    let mut start = from;
+    if let Ok(data) = fs::read(&cp_path) {
+        let cp: Checkpoint = json::from_slice(&data)?;
+        if cp.from == from && cp.last >= from && cp.last < to {
+            println!("从上次中断的位置继续: r{}", cp.last + 1);
+            start = cp.last + 1;
+        }
+    }
+    if let Some(dir) = cp_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let client = reqwest::blocking::Client::new();
+    let total = to - from + 1;
+    for rev in start..=to {
+        let params = endpoint::CommitParams {
+            repo_path: repo_path.to_owned(),
+            repo_name: repo_name.to_owned(),
+            rev: rev.to_string(),
+            force,
+            vcs: source::Vcs::Svn,
+            git_ref: None
+        };
+        print!("[{}/{}] r{} ... ", rev - from + 1, total, rev);
+        io::stdout().flush()?;
+        match endpoint::request_enqueue(&client, &params) {
+            Ok(id) => println!("已加入队列 #{}", id),
+            Err(e) => {
+                println!("失败");
+                return Err(format!("r{}加入队列失败: {}, 启动服务后重新执行相同的命令继续", rev, e).into());
+            }
+        }
+        fs::write(
+            &cp_path,
+            json::to_vec(&Checkpoint {
+                from,
+                last: rev
+            })?
+        )?;
+    }
+
+    //全部加入队列后清除进度
+    fs::remove_file(&cp_path)?;
+    println!("补录完成: r{}~r{}已加入队列，同步失败的提交可以通过dead_letter --list查看", from, to);
+
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//!
+//! Worktile对象ID的本地缓存
+//!
+//! 缓存代码托管平台、用户、仓库、分支、标签、状态的ID和访问令牌，
+//! 服务重启后不再重复查询，接口返回404时删除请求地址中的对象ID
+//!
+//! 缓存的ID属于打开时指定的代码托管平台，修改`product_name`后清空缓存；
+//! 访问令牌以明文保存，Unix下缓存文件只允许服务进程的用户读写
+//!
+
+use super::*;
+use journal::Journal;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+/// 缓存的键
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Key {
+    /// 代码托管平台
+    Product {
+        name: String
+    },
+    /// 代码托管平台的用户
+    User {
+        name: String
+    },
+    /// 代码仓库
+    Repository {
+        name: String
+    },
+    /// 代码仓库的分支
+    Branch {
+        repo: String,
+        name: String
+    },
+    /// 代码仓库的标签
+    Tag {
+        repo: String,
+        name: String
+    },
+    /// 工作项状态
+    State {
+        name: String
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Product {
+                name
+            } => write!(f, "product\t{}", name),
+            Key::User {
+                name
+            } => write!(f, "user\t{}", name),
+            Key::Repository {
+                name
+            } => write!(f, "repository\t{}", name),
+            Key::Branch {
+                repo,
+                name
+            } => write!(f, "branch\t{}:{}", repo, name),
+            Key::Tag {
+                repo,
+                name
+            } => write!(f, "tag\t{}:{}", repo, name),
+            Key::State {
+                name
+            } => write!(f, "state\t{}", name)
+        }
+    }
+}
+
+impl Key {
+    /// 是否为`parent`的下级对象(代码托管平台下的用户和仓库、仓库下的分支和标签)
+    pub fn belongs_to(&self, parent: &Key) -> bool {
+        match parent {
+            Key::Product {
+                ..
+            } => {
+                match self {
+                    Key::User {
+                        ..
+                    } |
+                    Key::Repository {
+                        ..
+                    } |
+                    Key::Branch {
+                        ..
+                    } |
+                    Key::Tag {
+                        ..
+                    } => true,
+                    _ => false
+                }
+            },
+            Key::Repository {
+                name
+            } => {
+                match self {
+                    Key::Branch {
+                        repo,
+                        ..
+                    } |
+                    Key::Tag {
+                        repo,
+                        ..
+                    } => repo == name,
+                    _ => false
+                }
+            },
+            _ => false
+        }
+    }
+}
+
+/// 缓存的访问令牌
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedToken {
+    pub token: String,
+    pub expires_in: chrono::NaiveDateTime,
+    #[serde(default)]
+    pub issued_at: Option<chrono::NaiveDateTime>
+}
+
+/// 记录文件的条目
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Put {
+        key: Key,
+        id: String
+    },
+    Remove {
+        key: Key
+    },
+    Token(CachedToken),
+    /// 缓存所属的代码托管平台(文件的第一条记录)
+    Product {
+        name: String
+    }
+}
+
+/// 缓存的记录文件
+pub struct IdCache {
+    journal: Journal<Record>,
+    /// 代码托管平台名称
+    product_name: String
+}
+
+impl IdCache {
+    /// 打开缓存并压缩记录文件，同时返回缓存的ID和访问令牌
+    /// 缓存属于其它代码托管平台(或没有记录平台)时清空缓存
+    pub fn open(
+        path: impl AsRef<std::path::Path>,
+        product_name: &str
+    ) -> Result<(IdCache, HashMap<Key, String>, Option<CachedToken>), AnyError> {
+        let (journal, records) = Journal::open(&path)?;
+        restrict_permissions(path.as_ref())?;
+        let mut cache = IdCache {
+            journal,
+            product_name: product_name.to_owned()
+        };
+        let mut ids = HashMap::new();
+        let mut token = None;
+        match records.first() {
+            Some(Record::Product {
+                name
+            }) if name == product_name => {},
+            _ => {
+                if !records.is_empty() {
+                    info!("cache is not for product {}, cleared", product_name);
+                }
+                cache.clear()?;
+                return Ok((cache, ids, token));
+            }
+        }
+        for record in records {
+            match record {
+                Record::Put {
+                    key,
+                    id
+                } => {
+                    ids.insert(key, id);
+                },
+                Record::Remove {
+                    key
+                } => {
+                    ids.remove(&key);
+                },
+                Record::Token(cached) => token = Some(cached),
+                Record::Product {
+                    ..
+                } => {}
+            }
+        }
+
+        //压缩记录文件，只保留当前的ID和最新的访问令牌
+        let mut entries: Vec<(&Key, &String)> = ids.iter().collect();
+        entries.sort();
+        let mut records = vec![Record::Product {
+            name: product_name.to_owned()
+        }];
+        records.extend(entries.into_iter().map(|(key, id)| {
+            Record::Put {
+                key: key.clone(),
+                id: id.clone()
+            }
+        }));
+        records.extend(token.clone().map(Record::Token));
+        cache.journal.rewrite(&records)?;
+
+        Ok((cache, ids, token))
+    }
+
+    /// 保存ID
+    pub fn put(&mut self, key: &Key, id: &str) -> Result<(), AnyError> {
+        self.journal.append(&Record::Put {
+            key: key.clone(),
+            id: id.to_owned()
+        })
+    }
+
+    /// 删除ID
+    pub fn remove(&mut self, key: &Key) -> Result<(), AnyError> {
+        self.journal.append(&Record::Remove {
+            key: key.clone()
+        })
+    }
+
+    /// 保存访问令牌
+    pub fn token(&mut self, token: &CachedToken) -> Result<(), AnyError> {
+        self.journal.append(&Record::Token(token.clone()))
+    }
+
+    /// 清空缓存(同时清除访问令牌)
+    pub fn clear(&mut self) -> Result<(), AnyError> {
+        self.journal.rewrite(&[Record::Product {
+            name: self.product_name.clone()
+        }])
+    }
+}
+
+/// 限制缓存文件只允许当前用户读写(文件中保存了访问令牌)
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path) -> Result<(), AnyError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("设置缓存文件: {} 的权限失败: {}", path.display(), e).into())
+}
+
+/// Windows下文件权限继承自所在目录，由安装目录的权限控制
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &std::path::Path) -> Result<(), AnyError> { Ok(()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_compacts_journal() {
+        let path = std::env::temp_dir().join(format!(
+            "svn_commit_wt-cache-{}-{}.log",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        let key = |name: &str| {
+            Key::Repository {
+                name: name.to_owned()
+            }
+        };
+        let token = |token: &str| {
+            CachedToken {
+                token: token.to_owned(),
+                expires_in: chrono::NaiveDateTime::from_timestamp(1_590_000_000, 0),
+                issued_at: None
+            }
+        };
+        {
+            let (mut cache, ..) = IdCache::open(&path, "SVN").unwrap();
+            cache.put(&key("a"), "r1").unwrap();
+            cache.put(&key("a"), "r2").unwrap();
+            cache.put(&key("b"), "r3").unwrap();
+            cache.remove(&key("b")).unwrap();
+            cache.token(&token("t1")).unwrap();
+            cache.token(&token("t2")).unwrap();
+        }
+
+        let (_cache, ids, cached) = IdCache::open(&path, "SVN").unwrap();
+        assert_eq!(ids.len(), 1);
+        assert_eq!(ids[&key("a")], "r2");
+        assert_eq!(cached.unwrap().token, "t2");
+        //平台、ID和访问令牌各一条记录
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,155 @@
+//!
+//! 工作项评论
+//!
+//! 同步提交时在提及的工作项上添加评论，内容由模板生成，
+//! 模板中可以使用: {author} {revision} {repo} {branch} {message} {files} {comment}
+//!
+
+use super::*;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+/// 默认的评论模板
+const DEFAULT_TEMPLATE: &'static str =
+    "{author} 提交了 {revision} ({repo}: {branch})\n{message}\n{comment}\n{files}";
+
+/// 评论中列出的最大文件数量
+const DEFAULT_MAX_FILES: usize = 10;
+
+/// 评论的配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CommentConfig {
+    /// 是否添加评论
+    pub enabled: bool,
+    pub template: String,
+    /// 列出的最大文件数量
+    pub max_files: usize
+}
+
+impl Default for CommentConfig {
+    fn default() -> Self {
+        CommentConfig {
+            enabled: true,
+            template: DEFAULT_TEMPLATE.to_owned(),
+            max_files: DEFAULT_MAX_FILES
+        }
+    }
+}
+
+impl CommentConfig {
+    /// 生成提交的评论内容
+    /// `author`为提交者对应成员的显示名，`comment`为提交日志中对该工作项的`#comment`内容
+    ///
+    /// 一次替换模板中的占位符，填入的内容中的`{...}`不再替换，未知的占位符原样保留
+    pub fn render(&self, meta: &worktile::CommitMeta, author: &str, comment: &[&str]) -> String {
+        let repos = distinct(meta.branches.iter().map(|branch| branch.repo.as_str()));
+        let branches = distinct(meta.branches.iter().map(|branch| branch.name.as_str()));
+        let value = |name: &str| -> Option<String> {
+            Some(match name {
+                "author" => author.to_owned(),
+                "revision" => meta.revision.to_owned(),
+                "repo" => repos.join(", "),
+                "branch" => branches.join(", "),
+                "message" => meta.message.trim().to_owned(),
+                "comment" => comment.join("\n"),
+                "files" => self.files_summary(meta),
+                _ => return None
+            })
+        };
+        let re = Regex::new(r"\{(\w+)\}").unwrap();
+        let mut lines = Vec::new();
+        for line in self.template.lines() {
+            let mut has_empty = false;
+            let text = re.replace_all(line, |caps: &Captures| {
+                match value(&caps[1]) {
+                    Some(value) => {
+                        has_empty |= value.is_empty();
+                        value
+                    },
+                    None => caps[0].to_owned()
+                }
+            });
+            //去除只有空占位符的行(如没有#comment时)，保留提交日志中的空行
+            if has_empty && text.trim().is_empty() {
+                continue;
+            }
+            lines.push(text.into_owned());
+        }
+        lines.join("\n")
+    }
+
+    /// 受影响文件的摘要，如: 新增1, 删除0, 修改2
+    fn files_summary(&self, meta: &worktile::CommitMeta) -> String {
+        let mut files = Vec::new();
+        let (mut added, mut removed, mut modified) = (0, 0, 0);
+        for branch in &meta.branches {
+            added += branch.files_added.len();
+            removed += branch.files_removed.len();
+            modified += branch.files_modified.len();
+            files.extend(branch.files_added.iter().map(|file| format!("A {}", file)));
+            files.extend(branch.files_removed.iter().map(|file| format!("D {}", file)));
+            files.extend(branch.files_modified.iter().map(|file| format!("M {}", file)));
+        }
+        let mut summary = format!("新增{}, 删除{}, 修改{}", added, removed, modified);
+        for file in files.iter().take(self.max_files) {
+            summary.push('\n');
+            summary.push_str(file);
+        }
+        if files.len() > self.max_files {
+            summary.push_str(&format!("\n...等{}个文件", files.len()));
+        }
+        summary
+    }
+}
+
+fn distinct<'a>(items: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut rv: Vec<&str> = Vec::new();
+    for item in items {
+        if !rv.contains(&item) {
+            rv.push(item);
+        }
+    }
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(message: &str) -> worktile::CommitMeta {
+        worktile::CommitMeta {
+            revision: "r5".to_owned(),
+            message: message.to_owned(),
+            committer_name: "zhangsan".to_owned(),
+            committed_at: chrono::NaiveDateTime::from_timestamp(1_590_000_000, 0),
+            branches: vec![worktile::CommitBranch {
+                repo: "repo".to_owned(),
+                name: "trunk".to_owned(),
+                tag: false,
+                sha: "sha".to_owned(),
+                ref_only: false,
+                files_added: vec!["trunk/a.txt".to_owned()],
+                files_removed: Vec::new(),
+                files_modified: Vec::new()
+            }]
+        }
+    }
+
+    #[test]
+    fn placeholders_in_content_are_not_expanded() {
+        let config = CommentConfig::default();
+        let text = config.render(&meta("#CD-7 {files} {comment}"), "张三", &["see {author}"]);
+        assert_eq!(text, "张三 提交了 r5 (repo: trunk)\n#CD-7 {files} {comment}\nsee {author}\n新增1, 删除0, 修改0\nA trunk/a.txt");
+    }
+
+    #[test]
+    fn only_empty_placeholder_lines_are_removed() {
+        let config = CommentConfig {
+            template: "{message}\n{comment}\n\n{unknown}".to_owned(),
+            ..Default::default()
+        };
+        let text = config.render(&meta("第一段\n\n第二段"), "张三", &[]);
+        assert_eq!(text, "第一段\n\n第二段\n\n{unknown}");
+    }
+}
@@ -0,0 +1,114 @@
+//!
+//! 同步失败的提交记录(死信)存储
+//!
+//! 重试次数用尽或不可重试的提交从队列移到这里，可以查看并重新放回队列
+//!
+
+use super::*;
+use journal::Journal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap, sync::{Arc, Mutex}
+};
+
+/// 同步失败的提交
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// 原队列任务
+    pub entry: queue::Entry,
+    /// 最后一次的错误信息
+    pub error: String,
+    /// Worktile接口错误的分类(其它错误时为空)
+    #[serde(default)]
+    pub category: Option<worktile::ErrorCategory>,
+    /// 已尝试的次数
+    pub attempts: u32,
+    pub failed_at: chrono::NaiveDateTime
+}
+
+/// 记录文件的条目
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Put(DeadLetter),
+    Remove {
+        id: u64
+    }
+}
+
+#[derive(Clone)]
+pub struct DeadLetters {
+    inner: Arc<Mutex<Inner>>
+}
+
+struct Inner {
+    journal: Journal<Record>,
+    letters: BTreeMap<u64, DeadLetter>
+}
+
+impl DeadLetters {
+    /// 打开存储
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<DeadLetters, AnyError> {
+        let (mut journal, records) = Journal::open(path)?;
+        let mut letters = BTreeMap::new();
+        for record in records {
+            match record {
+                Record::Put(letter) => {
+                    letters.insert(letter.entry.id, letter);
+                },
+                Record::Remove {
+                    id
+                } => {
+                    letters.remove(&id);
+                }
+            }
+        }
+
+        //压缩记录文件
+        let records: Vec<Record> = letters.values().cloned().map(Record::Put).collect();
+        journal.rewrite(&records)?;
+
+        Ok(DeadLetters {
+            inner: Arc::new(Mutex::new(Inner {
+                journal,
+                letters
+            }))
+        })
+    }
+
+    /// 添加失败的提交
+    pub fn put(&self, entry: queue::Entry, error: &AnyError, attempts: u32) -> Result<(), AnyError> {
+        let letter = DeadLetter {
+            entry,
+            error: error.to_string(),
+            category: error.downcast_ref::<worktile::ApiError>().map(|e| e.category()),
+            attempts,
+            failed_at: chrono::Local::now().naive_local()
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.journal.append(&Record::Put(letter.clone()))?;
+        inner.letters.insert(letter.entry.id, letter);
+        Ok(())
+    }
+
+    /// 失败的提交列表
+    pub fn list(&self) -> Vec<DeadLetter> { self.inner.lock().unwrap().letters.values().cloned().collect() }
+
+    /// 重新放入提交队列，返回新的任务序号
+    pub fn redrive(&self, id: u64, queue: &queue::CommitQueue) -> Result<u64, AnyError> {
+        let mut inner = self.inner.lock().unwrap();
+        let letter = inner.letters.get(&id).ok_or(format!("死信[{}]不存在", id))?;
+        let new_id = queue.push(letter.entry.params.clone())?;
+        inner.journal.append(&Record::Remove {
+            id
+        })?;
+        inner.letters.remove(&id);
+        Ok(new_id)
+    }
+
+    /// 全部重新放入提交队列
+    pub fn redrive_all(&self, queue: &queue::CommitQueue) -> Result<Vec<u64>, AnyError> {
+        let ids: Vec<u64> = self.inner.lock().unwrap().letters.keys().cloned().collect();
+        ids.into_iter().map(|id| self.redrive(id, queue)).collect()
+    }
+}
@@ -6,40 +6,195 @@ use super::*;
 use actix_rt::{Arbiter, System};
 use actix_web::*;
 use futures::future;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json as json;
 use std::sync::{Arc, Mutex};
 
-/// 调用本地HTTP服务提交代码记录
-pub fn request_commit(repo_path: &str, repo_name: &str, rev: &str) -> Result<(), AnyError> {
+/// 本地HTTP服务的地址
+fn local_url(uri: &str) -> Result<String, AnyError> {
     let cfg = settings::SharedConfig::load()?;
     let addr = cfg.config_string("http.listen");
     let port = addr.split(":").skip(1).next().unwrap_or("80");
-    reqwest::blocking::Client::new()
-        .post(&format!("http://127.0.0.1:{}/commit", port))
-        .json(&json::json!({
-            "repo_path": repo_path,
-            "repo_name": repo_name,
-            "rev": rev
-        }))
-        .send()?;
+    Ok(format!("http://127.0.0.1:{}/{}", port, uri))
+}
+
+/// 调用本地HTTP服务提交代码记录
+/// 未能放入队列时返回错误，钩子以非0退出代码结束
+pub fn request_commit(params: &CommitParams) -> Result<(), AnyError> {
+    request_enqueue(&reqwest::blocking::Client::new(), params)?;
+    Ok(())
+}
+
+/// 调用本地HTTP服务将提交放入队列，返回队列中的序号
+pub fn request_enqueue(client: &reqwest::blocking::Client, params: &CommitParams) -> Result<u64, AnyError> {
+    let resp = client.post(&local_url("commit")?).json(params).send()?;
+    let status = resp.status();
+    let resp: json::Value = resp.json().unwrap_or_default();
+    match resp["id"].as_u64() {
+        Some(id) if status.is_success() => Ok(id),
+        _ => Err(format!("加入队列失败: {} {}", status, resp["msg"].as_str().unwrap_or("未知错误")).into())
+    }
+}
+
+/// 调用本地HTTP服务打印同步失败的提交列表
+pub fn request_dead_letters() -> Result<(), AnyError> {
+    let letters: Vec<dead_letter::DeadLetter> = reqwest::blocking::Client::new()
+        .get(&local_url("dead_letters")?)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    for letter in &letters {
+        let params = &letter.entry.params;
+        println!(
+            "#{}\t{}\t{}\t尝试{}次\t{}{}",
+            letter.entry.id,
+            params,
+            letter.failed_at.format("%Y-%m-%d %H:%M:%S"),
+            letter.attempts,
+            letter.category.map(|category| format!("[{:?}] ", category)).unwrap_or_default(),
+            letter.error
+        );
+    }
+    println!("共{}条", letters.len());
+    Ok(())
+}
+
+/// 调用本地HTTP服务将同步失败的提交重新放入队列
+/// 未指定序号时全部重新放入
+pub fn request_redrive(id: Option<u64>) -> Result<(), AnyError> {
+    let uri = match id {
+        Some(id) => format!("dead_letters/{}/redrive", id),
+        None => "dead_letters/redrive".to_owned()
+    };
+    let resp: json::Value =
+        reqwest::blocking::Client::new().post(&local_url(&uri)?).send()?.error_for_status()?.json()?;
+    println!("{}", resp["msg"].as_str().unwrap_or_default());
+    Ok(())
+}
+
+/// 调用本地HTTP服务打印未找到对应成员的提交者
+pub fn request_unmapped_authors() -> Result<(), AnyError> {
+    let authors: Vec<members::UnmappedAuthor> = reqwest::blocking::Client::new()
+        .get(&local_url("members/unmapped")?)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    for author in &authors {
+        println!(
+            "{}\t提交{}个版本\t{}\t{}",
+            author.name,
+            author.commits,
+            author.last_revision,
+            author.last_seen.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    println!("共{}个", authors.len());
     Ok(())
 }
 
+/// 调用本地HTTP服务查找提交者对应的成员
+pub fn request_member(author: &str) -> Result<Option<worktile::Member>, AnyError> {
+    let resp: json::Value = reqwest::blocking::Client::new()
+        .get(&local_url("members/lookup")?)
+        .query(&[("author", author)])
+        .send()?
+        .json()?;
+    if resp["status"].as_i64() != Some(0) {
+        return Err(format!("查找成员失败: {}", resp["msg"].as_str().unwrap_or("未知错误")).into());
+    }
+    Ok(json::from_value(resp["member"].clone())?)
+}
+
+/// 调用本地HTTP服务检查工作项是否存在
+/// 本地服务不可用或查询Worktile失败时返回错误
+pub async fn request_work_item_exists(client: &reqwest::Client, identifier: &str) -> Result<bool, AnyError> {
+    let resp: json::Value = client
+        .get(&local_url("work_items")?)
+        .query(&[("identifier", identifier)])
+        .send()
+        .await
+        .map_err(|e| format!("无法连接本地服务: {}", e))?
+        .json()
+        .await?;
+    resp["found"].as_bool().ok_or_else(|| resp["msg"].as_str().unwrap_or("未知错误").to_owned().into())
+}
+
+/// 调用本地HTTP服务打印缓存的Worktile对象ID
+pub fn request_cache() -> Result<(), AnyError> {
+    let resp: json::Value =
+        reqwest::blocking::Client::new().get(&local_url("cache")?).send()?.error_for_status()?.json()?;
+    let ids: Vec<(String, String)> = json::from_value(resp["ids"].clone())?;
+    for (key, id) in &ids {
+        println!("{}\t{}", key, id);
+    }
+    println!("共{}条", ids.len());
+    match resp["token_expires_in"].as_str() {
+        Some(expires_in) => println!("访问令牌过期时间: {}", expires_in),
+        None => println!("访问令牌: 无")
+    }
+    Ok(())
+}
+
+/// 调用本地HTTP服务清空缓存的Worktile对象ID和访问令牌
+pub fn request_clear_cache() -> Result<(), AnyError> {
+    let resp: json::Value = reqwest::blocking::Client::new()
+        .post(&local_url("cache/clear")?)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    println!("{}", resp["msg"].as_str().unwrap_or_default());
+    Ok(())
+}
+
+/// 根据配置创建Worktile客户端接口
+pub fn worktile_client(cfg: &settings::SharedConfig) -> Result<worktile::Client, AnyError> {
+    worktile::Client::build()
+        .product_name(cfg.config_string("worktile.product_name"))
+        .credential(cfg.config_string("worktile.client_id"), cfg.config_string("worktile.client_secret"))
+        .cache_path(cfg.config_string("worktile.cache_path"))
+        .workflow(workflow::Workflow::load(cfg)?)
+        .comment(cfg.config_or_default("comment")?)
+        .worklog(cfg.config_or_default("worklog")?)
+        .members(cfg.config_or_default("members")?)
+        .error_codes(cfg.config_or_default("worktile.error_codes")?)
+        .build()
+}
+
 /// 启动HTTP服务
 pub fn http_serve(stop_signer: Option<oneshot::Receiver<()>>) -> Result<u16, AnyError> {
     let cfg = settings::SharedConfig::load()?;
 
+    //先绑定端口，已有服务在运行时直接退出，记录文件(队列、台账、缓存)只由一个服务进程打开和压缩
+    let addr = cfg.config_string("http.listen");
+    let listener =
+        std::net::TcpListener::bind(&addr).map_err(|e| format!("http server bind failed: {}", e))?;
+
     //Worktile客户端接口
-    let wt = worktile::Client::build()
-        .product_name(cfg.config_string("worktile.product_name"))
-        .credential(cfg.config_string("worktile.client_id"), cfg.config_string("worktile.client_secret"))
-        .build()?;
+    let wt = worktile_client(&cfg)?;
+
+    //提交队列
+    let queue = queue::CommitQueue::open(cfg.config_string("queue.path"))?;
+    let dead_letters = dead_letter::DeadLetters::open(cfg.config_string("queue.dead_letter_path"))?;
+    let policy: retry::RetryPolicy = cfg.config_or_default("retry")?;
+    let ctx = SyncContext::new(&cfg, wt.clone())?;
 
     //创建Actix运行时
     let mut system = System::new("main");
     let arbiter = Arbiter::current();
 
+    //启动队列的后台同步任务
+    {
+        let queue = queue.clone();
+        let dead_letters = dead_letters.clone();
+        arbiter.exec_fn(move || actix_rt::spawn(queue::worker(queue, dead_letters, ctx, policy)));
+    }
+
+    //启动访问令牌的后台刷新任务
+    {
+        let wt = wt.clone();
+        arbiter.exec_fn(move || actix_rt::spawn(wt.refresh_token_loop()));
+    }
+
     let http_srv: Arc<Mutex<Option<dev::Server>>> = Arc::new(Mutex::new(None));
 
     //如果有停止信号的通道则监听事件(由SCM触发)
@@ -56,28 +211,40 @@ pub fn http_serve(stop_signer: Option<oneshot::Receiver<()>>) -> Result<u16, Any
     }
 
     //创建HTTP服务
-    let addr = cfg.config_string("http.listen");
     let srv = HttpServer::new(move || {
         App::new()
             .app_data(wt.clone())
+            .app_data(queue.clone())
+            .app_data(dead_letters.clone())
             .wrap(middleware::NormalizePath)
             .wrap(middleware::Compress::default())
             .wrap(middleware::Logger::new(&cfg.config_string("http.log_format")))
             .service(commit)
+            .service(list_dead_letters)
+            .service(redrive_dead_letter)
+            .service(redrive_dead_letters)
+            .service(list_unmapped_authors)
+            .service(lookup_member)
+            .service(find_work_item)
+            .service(list_cache)
+            .service(clear_cache)
     })
-    .bind(addr)
-    .map_err(|e| format!("http server bind failed: {}", e))?
+    .listen(listener)
+    .map_err(|e| format!("http server listen failed: {}", e))?
     .run();
 
     //将Server保存在全局的Application中，用于退出
     *http_srv.lock().unwrap() = Some(srv.clone());
 
+    //端口已绑定，通知服务管理器
+    service::notify_ready();
+
     //进入HTTP服务事件循环
     let http_srv_exit_status = system.block_on(srv);
 
     http_srv_exit_status?;
 
-    Ok(win_service::exit_code::OK)
+    Ok(service::exit_code::OK)
 }
 
 impl FromRequest for worktile::Client {
@@ -91,71 +258,361 @@ impl FromRequest for worktile::Client {
     }
 }
 
+impl FromRequest for queue::CommitQueue {
+    type Config = ();
+    type Error = ();
+    type Future = future::Ready<Result<Self, Self::Error>>;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        future::ok(req.app_data::<queue::CommitQueue>().unwrap().clone())
+    }
+}
+
+impl FromRequest for dead_letter::DeadLetters {
+    type Config = ();
+    type Error = ();
+    type Future = future::Ready<Result<Self, Self::Error>>;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        future::ok(req.app_data::<dead_letter::DeadLetters>().unwrap().clone())
+    }
+}
+
 /// 代码提交请求
-#[derive(Debug, Deserialize)]
-struct CommitParams {
-    repo_path: String,
-    repo_name: String,
-    rev: String
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitParams {
+    pub repo_path: String,
+    pub repo_name: String,
+    pub rev: String,
+    /// 强制同步已同步过的版本
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default)]
+    pub vcs: source::Vcs,
+    /// Git推送的引用名称(如refs/heads/master)
+    #[serde(default)]
+    pub git_ref: Option<String>
+}
+
+impl std::fmt::Display for CommitParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.vcs, &self.git_ref) {
+            (source::Vcs::Git, Some(name)) => write!(f, "{}@{}({})", self.repo_name, self.rev, name),
+            _ => write!(f, "{}@r{}", self.repo_name, self.rev)
+        }
+    }
+}
+
+impl CommitParams {
+    /// 台账中的版本
+    /// Git提交推送到不同引用时分别同步，为`SHA值@引用名称`
+    pub fn ledger_rev(&self) -> String {
+        match (self.vcs, &self.git_ref) {
+            (source::Vcs::Git, Some(name)) => format!("{}@{}", self.rev, name),
+            _ => self.rev.to_owned()
+        }
+    }
 }
 
 #[post("/commit")]
-async fn commit(wt: worktile::Client, params: web::Json<CommitParams>) -> HttpResponse {
-    let meta = match commit_meta_from_svn(&params.repo_path, &params.rev).await {
-        Ok(meta) => meta,
+async fn commit(queue: queue::CommitQueue, params: web::Json<CommitParams>) -> HttpResponse {
+    //写入队列后再答复，由后台任务异步提交
+    match queue.push(params.into_inner()) {
+        Ok(id) => {
+            HttpResponse::Ok().json(json::json!({
+                "status": 0,
+                "msg": "成功",
+                "id": id
+            }))
+        },
         Err(e) => {
-            return HttpResponse::BadRequest().json(json::json!({
+            error!("commit enqueue failed: {}", e);
+            HttpResponse::InternalServerError().json(json::json!({
                 "status": -1,
                 "msg": e.to_string()
-            }));
+            }))
         }
-    };
-    let branch = match svn::commit_branch(&params.repo_path, &params.rev).await {
-        Ok(branch) => {
-            //默认分支为trunk
-            branch.unwrap_or("trunk".to_owned())
+    }
+}
+
+#[get("/dead_letters")]
+async fn list_dead_letters(dead_letters: dead_letter::DeadLetters) -> HttpResponse {
+    HttpResponse::Ok().json(dead_letters.list())
+}
+
+#[post("/dead_letters/{id}/redrive")]
+async fn redrive_dead_letter(
+    queue: queue::CommitQueue,
+    dead_letters: dead_letter::DeadLetters,
+    path: web::Path<(u64,)>
+) -> HttpResponse {
+    match dead_letters.redrive(path.0, &queue) {
+        Ok(id) => {
+            HttpResponse::Ok().json(json::json!({
+                "status": 0,
+                "msg": format!("死信[{}]已重新放入队列[{}]", path.0, id),
+                "id": id
+            }))
         },
         Err(e) => {
-            return HttpResponse::BadRequest().json(json::json!({
+            HttpResponse::BadRequest().json(json::json!({
                 "status": -1,
                 "msg": e.to_string()
-            }));
-        }
-    };
+            }))
+        },
+    }
+}
 
-    //异步提交
-    actix_rt::spawn(async move {
-        let _ = wt.commit(&params.repo_name, &branch, meta).await;
-    });
+#[post("/dead_letters/redrive")]
+async fn redrive_dead_letters(
+    queue: queue::CommitQueue,
+    dead_letters: dead_letter::DeadLetters
+) -> HttpResponse {
+    match dead_letters.redrive_all(&queue) {
+        Ok(ids) => {
+            HttpResponse::Ok().json(json::json!({
+                "status": 0,
+                "msg": format!("{}条死信已重新放入队列", ids.len()),
+                "ids": ids
+            }))
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().json(json::json!({
+                "status": -1,
+                "msg": e.to_string()
+            }))
+        },
+    }
+}
 
+#[get("/members/unmapped")]
+async fn list_unmapped_authors(wt: worktile::Client) -> HttpResponse {
+    HttpResponse::Ok().json(wt.unmapped_authors())
+}
+
+/// 查找成员的参数
+#[derive(Deserialize)]
+struct MemberQuery {
+    author: String
+}
+
+#[get("/members/lookup")]
+async fn lookup_member(wt: worktile::Client, query: web::Query<MemberQuery>) -> HttpResponse {
+    match wt.member(&query.author).await {
+        Ok(member) => {
+            HttpResponse::Ok().json(json::json!({
+                "status": 0,
+                "member": member
+            }))
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().json(json::json!({
+                "status": -1,
+                "msg": e.to_string()
+            }))
+        },
+    }
+}
+
+/// 查询工作项的参数
+#[derive(Deserialize)]
+struct WorkItemQuery {
+    identifier: String
+}
+
+/// 查询工作项是否存在(提交前检查使用)，失败时返回提交者可以理解的错误信息
+#[get("/work_items")]
+async fn find_work_item(wt: worktile::Client, query: web::Query<WorkItemQuery>) -> HttpResponse {
+    match wt.work_item(&query.identifier).await {
+        Ok(item) => {
+            HttpResponse::Ok().json(json::json!({
+                "status": 0,
+                "found": item.is_some()
+            }))
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().json(json::json!({
+                "status": -1,
+                "msg": precommit::user_message(e).to_string()
+            }))
+        },
+    }
+}
+
+#[get("/cache")]
+async fn list_cache(wt: worktile::Client) -> HttpResponse {
+    let (ids, token_expires_in) = wt.cached_ids();
     HttpResponse::Ok().json(json::json!({
-        "status": 0,
-        "msg": "成功"
+        "ids": ids,
+        "token_expires_in": token_expires_in.map(|expires_in| expires_in.format("%Y-%m-%d %H:%M:%S").to_string())
     }))
 }
 
-/// 从SVN提交记录里提取Worktile需要的元数据
-async fn commit_meta_from_svn(repo_path: &str, rev: &str) -> Result<worktile::CommitMeta, AnyError> {
-    use rand::{thread_rng, Rng};
-
-    //填充随机数满足40位SHA值
-    //0-9 a-f
-    let sha = format!(
-        "{}{}{}",
-        rev,
-        " ".repeat(10 - rev.len().min(10)),
-        hex::encode((0..15).map(|_| thread_rng().gen::<u8>()).collect::<Vec<u8>>())
-    );
-    let files_changed = svn::commit_changed(repo_path, rev).await?;
-    let meta = worktile::CommitMeta {
-        sha,
-        message: svn::commit_message(repo_path, rev).await?,
-        committer_name: svn::commit_author(repo_path, rev).await?,
-        committed_at: svn::commit_date(repo_path, rev).await?,
-        files_added: files_changed.added,
-        files_removed: files_changed.removed,
-        files_modified: files_changed.modified
+#[post("/cache/clear")]
+async fn clear_cache(wt: worktile::Client) -> HttpResponse {
+    match wt.clear_cache() {
+        Ok(_) => {
+            HttpResponse::Ok().json(json::json!({
+                "status": 0,
+                "msg": "缓存已清空"
+            }))
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().json(json::json!({
+                "status": -1,
+                "msg": e.to_string()
+            }))
+        },
+    }
+}
+
+/// 同步提交记录的上下文
+#[derive(Clone)]
+pub struct SyncContext {
+    pub cfg: settings::SharedConfig,
+    pub wt: worktile::Client,
+    pub ledger: ledger::Ledger
+}
+
+impl SyncContext {
+    pub fn new(cfg: &settings::SharedConfig, wt: worktile::Client) -> Result<SyncContext, AnyError> {
+        Ok(SyncContext {
+            cfg: cfg.clone(),
+            wt,
+            ledger: ledger::Ledger::open(cfg.config_string("ledger.path"))?
+        })
+    }
+}
+
+/// 提取SVN或Git提交记录并同步到Worktile
+/// 已同步的版本跳过(除非指定了强制同步)，未完成的版本从中断的步骤继续
+pub async fn sync_commit(ctx: &SyncContext, params: &CommitParams) -> Result<(), AnyError> {
+    let rev = params.ledger_rev();
+    let mut entry = match ctx.ledger.get(&params.repo_name, &rev) {
+        Some(entry) if entry.status == ledger::Status::Synced => {
+            if !params.force {
+                info!("{} already synced, skipped", params);
+                return Ok(());
+            }
+            ledger::LedgerEntry::new(&params.repo_name, &rev)
+        },
+        Some(entry) => entry,
+        None => ledger::LedgerEntry::new(&params.repo_name, &rev)
+    };
+
+    let source = source::open(&ctx.cfg, params)?;
+    let meta = source::commit_meta(source.commit(&params.rev).await?, &params.repo_name);
+    let rv = ctx.wt.commit(meta, &mut entry.progress).await;
+
+    //无论成功与否都保存进度
+    entry.status = if rv.is_ok() {
+        ledger::Status::Synced
+    } else {
+        ledger::Status::Pending
     };
+    ctx.ledger.put(entry)?;
+
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock_worktile::MockWorktile, svn_fixture::TestRepo};
+    use actix_web::test;
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn commit_endpoint_syncs_revision() {
+        let repo = TestRepo::create();
+        repo.write("trunk/a.txt", "a");
+        let rev = repo.commit("zhangsan", "#CD-7 修复");
+        let wt = MockWorktile::start();
+        wt.add_work_item("CD-7", "task", "新建");
+        let cfg = settings::SharedConfig::from_toml(&format!(
+            "[ledger]\npath = '{}'\n[queue]\npath = '{}'",
+            repo.temp("ledger.journal"),
+            repo.temp("queue.journal")
+        ))
+        .unwrap();
+        let ctx = SyncContext::new(&cfg, wt.client().build().unwrap()).unwrap();
+        let queue = queue::CommitQueue::open(cfg.config_string("queue.path")).unwrap();
+
+        //提交请求写入队列
+        let mut app = test::init_service(App::new().app_data(queue.clone()).service(commit)).await;
+        let req = test::TestRequest::post()
+            .uri("/commit")
+            .set_json(&json::json!({
+                "repo_path": repo.path(),
+                "repo_name": "repo",
+                "rev": rev.to_string()
+            }))
+            .to_request();
+        let resp: json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["status"], json::json!(0));
+        assert_eq!(queue.len(), 1);
+
+        //按后台任务的方式同步队列中的提交
+        let entry = queue.next().await;
+        sync_commit(&ctx, &entry.params).await.unwrap();
+        queue.done(entry.id).unwrap();
+
+        let commits = wt.objects("commits");
+        assert_eq!(commits.len(), 1);
+        let body = &commits[0].body;
+        assert_eq!(
+            body["sha"],
+            json::json!(source::commit_sha(&repo.uuid(), &rev.to_string(), None).unwrap())
+        );
+        assert_eq!(body["message"], json::json!("#CD-7 修复"));
+        assert_eq!(body["files_added"], json::json!(["trunk", "trunk/a.txt"]));
+        assert_eq!(body["work_item_identifiers"], json::json!(["CD-7"]));
+        assert_eq!(wt.objects("branches")[0].name, "trunk");
+        let entry = ctx.ledger.get("repo", &rev.to_string()).unwrap();
+        assert!(entry.status == ledger::Status::Synced);
 
-    Ok(meta)
+        //已同步的版本不再提交
+        wt.clear_calls();
+        sync_commit(&ctx, &commit_params(&repo, rev)).await.unwrap();
+        assert!(wt.calls().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn lookup_endpoints_query_worktile() {
+        let wt = MockWorktile::start();
+        wt.add_work_item("CD-7", "task", "新建");
+        wt.add_member("zhangsan", "张三", "zhangsan@example.com");
+        let mut app = test::init_service(
+            App::new().app_data(wt.client().build().unwrap()).service(find_work_item).service(lookup_member)
+        )
+        .await;
+
+        for (identifier, found) in &[("CD-7", true), ("CD-9", false)] {
+            let req =
+                test::TestRequest::get().uri(&format!("/work_items?identifier={}", identifier)).to_request();
+            let resp: json::Value = test::read_response_json(&mut app, req).await;
+            assert_eq!(resp["found"], json::json!(found));
+        }
+
+        let req = test::TestRequest::get().uri("/members/lookup?author=zhangsan").to_request();
+        let resp: json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["member"]["display_name"], json::json!("张三"));
+        let req = test::TestRequest::get().uri("/members/lookup?author=lisi").to_request();
+        let resp: json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["status"], json::json!(0));
+        assert!(resp["member"].is_null());
+    }
+
+    fn commit_params(repo: &TestRepo, rev: u64) -> CommitParams {
+        CommitParams {
+            repo_path: repo.path(),
+            repo_name: "repo".to_owned(),
+            rev: rev.to_string(),
+            force: false,
+            vcs: source::Vcs::Svn,
+            git_ref: None
+        }
+    }
 }
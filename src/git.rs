@@ -0,0 +1,225 @@
+//!
+//! Git提交记录提取封装
+//!
+//! 通过git命令读取提交记录，`post-receive`钩子将推送的提交按顺序放入提交队列
+//!
+
+use super::{endpoint, source, svn, AnyError};
+use tokio::process::Command;
+
+/// 删除或新建引用时`post-receive`传入的空SHA值
+const ZERO_SHA: &'static str = "0000000000000000000000000000000000000000";
+
+/// 引用名称的前缀
+const HEADS_PREFIX: &'static str = "refs/heads/";
+const TAGS_PREFIX: &'static str = "refs/tags/";
+
+/// 提交记录
+#[derive(Debug)]
+pub struct CommitInfo {
+    pub author: String,
+    pub date: chrono::NaiveDateTime,
+    pub message: String
+}
+
+/// 通过git命令访问仓库
+#[derive(Debug, Clone)]
+pub struct Git {
+    repo_path: String
+}
+
+impl Git {
+    pub fn new(repo_path: &str) -> Git {
+        Git {
+            repo_path: repo_path.to_owned()
+        }
+    }
+
+    /// 读取提交的作者、日期和日志
+    pub async fn commit(&self, sha: &str) -> Result<CommitInfo, AnyError> {
+        let output = self.run(&["show", "-s", "--encoding=UTF-8", "--format=%an%n%aI%n%B", sha]).await?;
+        let mut lines = output.splitn(3, '\n');
+        let (author, date) = match (lines.next(), lines.next()) {
+            (Some(author), Some(date)) => (author.to_owned(), date),
+            _ => return Err(format!("无法解析提交[{}]: {}", sha, output).into())
+        };
+        let date = chrono::DateTime::parse_from_rfc3339(date)
+            .map(|dtt| dtt.naive_utc())
+            .map_err(|e| format!("解析日期: {}, 失败: {}", date, e))?;
+        Ok(CommitInfo {
+            author,
+            date,
+            message: lines.next().unwrap_or_default().trim_end().to_owned()
+        })
+    }
+
+    /// 提交受影响的文件(合并提交与第一个父提交比较)
+    pub async fn changed(&self, sha: &str) -> Result<svn::FilesChanged, AnyError> {
+        let output = self
+            .run(&[
+                "diff-tree",
+                "-r",
+                "-z",
+                "--root",
+                "-m",
+                "--first-parent",
+                "--no-commit-id",
+                "--name-status",
+                sha
+            ])
+            .await?;
+        parse_name_status(&output)
+    }
+
+    /// 将引用解析为提交的SHA值(附注标签解析为指向的提交)
+    pub async fn resolve_commit(&self, rev: &str) -> Result<String, AnyError> {
+        self.run(&["rev-parse", "--verify", &format!("{}^{{commit}}", rev)])
+            .await
+            .map(|sha| sha.trim().to_owned())
+    }
+
+    /// 引用更新后新增的提交(从旧到新)，排除`pushed`之外其它分支和标签已包含的提交
+    pub async fn new_commits(
+        &self,
+        old: &str,
+        new: &str,
+        pushed: &[String]
+    ) -> Result<Vec<String>, AnyError> {
+        let mut args = vec!["rev-list".to_owned(), "--reverse".to_owned(), new.to_owned()];
+        if old != ZERO_SHA {
+            args.push(format!("^{}", old));
+        }
+        args.push("--not".to_owned());
+        //--exclude只作用于下一个--branches或--tags，名称不包括refs/heads/或refs/tags/
+        for (prefix, group) in &[(HEADS_PREFIX, "--branches"), (TAGS_PREFIX, "--tags")] {
+            for name in pushed.iter().filter(|name| name.starts_with(prefix)) {
+                args.push(format!("--exclude={}", &name[prefix.len()..]));
+            }
+            args.push(group.to_string());
+        }
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        let output = self.run(&args).await?;
+        Ok(output.lines().map(|line| line.trim().to_owned()).filter(|sha| !sha.is_empty()).collect())
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<String, AnyError> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.repo_path)
+            .args(&["-c", "core.quotepath=false"])
+            .args(args)
+            .output()
+            .await?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            let err = String::from_utf8_lossy(&output.stderr);
+            let mut err = err.trim();
+            if err.is_empty() {
+                err = "(EMPTY)";
+            }
+            warn!("git {}, stderr: {}", args.join(" "), err);
+            Err(format!("git {}, {}", args.join(" "), err).into())
+        }
+    }
+}
+
+/// 解析`git diff-tree -z --name-status`的输出
+/// 依次为以\0分隔的变更类型和路径
+fn parse_name_status(output: &str) -> Result<svn::FilesChanged, AnyError> {
+    let mut paths = Vec::new();
+    let mut fields = output.split('\0').filter(|field| !field.is_empty());
+    while let Some(status) = fields.next() {
+        let path = fields.next().ok_or(format!("无法解析受影响的路径: {}", status))?;
+        let status = match status.chars().next() {
+            Some('A') => svn::ChangeStatus::Added,
+            Some('D') => svn::ChangeStatus::Deleted,
+            //内容或类型修改
+            Some('M') | Some('T') => svn::ChangeStatus::Updated,
+            _ => return Err(format!("未知的变更类型: {}", status).into())
+        };
+        paths.push(svn::ChangedPath {
+            path: path.to_owned(),
+            status,
+            props_changed: false,
+            copied_from: None,
+            kind: svn::NodeKind::File
+        });
+    }
+    Ok(svn::FilesChanged {
+        paths
+    })
+}
+
+/// 引用对应的分支或标签名称
+pub fn branch_from_ref(name: &str) -> Option<(String, bool)> {
+    if name.starts_with(HEADS_PREFIX) {
+        Some((name[HEADS_PREFIX.len()..].to_owned(), false))
+    } else if name.starts_with(TAGS_PREFIX) {
+        Some((name[TAGS_PREFIX.len()..].to_owned(), true))
+    } else {
+        None
+    }
+}
+
+/// 处理`post-receive`钩子的输入(每行为: 旧SHA 新SHA 引用名称)
+/// 将推送的提交按顺序提交到本地HTTP服务，新的标签只创建引用
+pub fn post_receive(repo_path: &str, repo_name: &str, input: &str, force: bool) -> Result<(), AnyError> {
+    let mut updates = Vec::new();
+    for line in input.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [old, new, name] => updates.push((old.to_string(), new.to_string(), name.to_string())),
+            [] => continue,
+            _ => return Err(format!("无法解析post-receive的输入: {}", line).into())
+        }
+    }
+    //本次推送更新的全部引用，其它引用已包含的提交不再提交
+    let pushed: Vec<String> = updates.iter().map(|(_, _, name)| name.to_owned()).collect();
+
+    let git = Git::new(repo_path);
+    let repo_path = repo_path.to_owned();
+    let repo_name = repo_name.to_owned();
+    let mut system = actix_rt::System::new("post-receive");
+    let commits = system.block_on(async move {
+        let mut commits: Vec<endpoint::CommitParams> = Vec::new();
+        for (old, new, name) in &updates {
+            //删除引用
+            if new == ZERO_SHA {
+                continue;
+            }
+            let tag = match branch_from_ref(name) {
+                Some((_, tag)) => tag,
+                None => continue
+            };
+            let shas = if tag {
+                vec![git.resolve_commit(new).await?]
+            } else {
+                git.new_commits(old, new, &pushed).await?
+            };
+            for sha in shas {
+                //同一引用的相同提交只提交一次，推送到多个分支时每个分支分别同步(台账按`SHA值@引用名称`记录)
+                if commits
+                    .iter()
+                    .any(|params| params.rev == sha && params.git_ref.as_deref() == Some(name.as_str()))
+                {
+                    continue;
+                }
+                commits.push(endpoint::CommitParams {
+                    repo_path: repo_path.clone(),
+                    repo_name: repo_name.clone(),
+                    rev: sha,
+                    force,
+                    vcs: source::Vcs::Git,
+                    git_ref: Some(name.to_owned())
+                });
+            }
+        }
+        Ok::<_, AnyError>(commits)
+    })?;
+
+    for params in &commits {
+        endpoint::request_commit(params)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//!
+//! 追加写入的记录文件封装
+//!
+//! 每行一条JSON记录，写入后立即同步到磁盘
+//!
+
+use super::AnyError;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json as json;
+use std::{
+    fs::{self, File, OpenOptions}, io::{BufRead, BufReader, Write}, marker::PhantomData, path::{Path, PathBuf}
+};
+
+pub struct Journal<R> {
+    path: PathBuf,
+    file: File,
+    _record: PhantomData<R>
+}
+
+impl<R> Journal<R>
+where
+    R: Serialize + DeserializeOwned
+{
+    /// 打开记录文件(不存在则创建)，同时返回文件中已有的记录
+    pub fn open(path: impl AsRef<Path>) -> Result<(Journal<R>, Vec<R>), AnyError> {
+        let path = path.as_ref().to_owned();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("创建目录: {}, 失败: {}", dir.display(), e))?;
+        }
+        let mut records = Vec::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for (idx, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match json::from_str(&line) {
+                    Ok(record) => records.push(record),
+                    //进程中断时最后一行可能不完整，忽略损坏的记录
+                    Err(e) => warn!("journal {}, line {} corrupted: {}", path.display(), idx + 1, e)
+                }
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok((
+            Journal {
+                path,
+                file,
+                _record: PhantomData
+            },
+            records
+        ))
+    }
+
+    /// 追加记录
+    pub fn append(&mut self, record: &R) -> Result<(), AnyError> {
+        let mut line = json::to_string(record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// 使用指定的记录重写文件(压缩)，保留原文件的权限
+    pub fn rewrite(&mut self, records: &[R]) -> Result<(), AnyError> {
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.set_permissions(self.file.metadata()?.permissions())?;
+            for record in records {
+                let mut line = json::to_string(record)?;
+                line.push('\n');
+                file.write_all(line.as_bytes())?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//!
+//! SVN仓库的目录结构规则
+//!
+//! 按顺序使用规则匹配受影响的路径，提取项目和分支名称，
+//! 规则通过命名捕获组`project`和`branch`指定项目和分支在路径中的位置
+//!
+
+use super::*;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// 默认规则，提取branches、branch路径的分支名称和tags路径的标签名称
+const DEFAULT_RULES: &[(&'static str, bool)] =
+    &[(r"(?:^|/)(?:branches|branch)/(?P<branch>[^/]+)", false), (r"(?:^|/)tags/(?P<branch>[^/]+)", true)];
+
+/// 默认分支
+const DEFAULT_BRANCH: &'static str = "trunk";
+
+/// 目录结构规则
+#[derive(Debug, Clone, Deserialize)]
+pub struct LayoutRule {
+    /// 匹配路径的正则表达式
+    pub pattern: String,
+    /// 固定的分支名称(规则没有`branch`捕获组时使用)
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// 是否为标签目录
+    #[serde(default)]
+    pub tag: bool
+}
+
+/// 目录结构配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct LayoutConfig {
+    rules: Vec<LayoutRule>,
+    default_branch: Option<String>
+}
+
+/// 仓库的配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct RepositoryConfig {
+    layout: Option<LayoutConfig>
+}
+
+/// 分支信息
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BranchRef {
+    /// 项目名称
+    pub project: Option<String>,
+    /// 分支名称
+    pub name: String,
+    /// 是否为标签
+    pub tag: bool
+}
+
+impl BranchRef {
+    /// 对应的Worktile代码仓库名称
+    /// 有项目名称时为`仓库名称/项目名称`
+    pub fn repository(&self, repo_name: &str) -> String {
+        match &self.project {
+            Some(project) => format!("{}/{}", repo_name, project),
+            None => repo_name.to_owned()
+        }
+    }
+}
+
+pub struct Layout {
+    rules: Vec<(Regex, LayoutRule)>,
+    default_branch: String
+}
+
+impl Layout {
+    /// 加载仓库的目录结构规则
+    /// 优先使用`[repositories.仓库名称.layout]`，其次为`[layout]`，都未配置时使用默认规则
+    pub fn load(cfg: &settings::SharedConfig, repo_name: &str) -> Result<Layout, AnyError> {
+        let mut repos: HashMap<String, RepositoryConfig> = cfg.config_or_default("repositories")?;
+        let config = match repos.remove(repo_name).and_then(|repo| repo.layout) {
+            Some(config) => config,
+            None => cfg.config_or_default("layout")?
+        };
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            let re = Regex::new(&rule.pattern)
+                .map_err(|e| format!("目录结构规则[{}]无效: {}", rule.pattern, e))?;
+            if rule.branch.is_none() && !re.capture_names().any(|name| name == Some("branch")) {
+                return Err(format!("目录结构规则[{}]缺少branch捕获组或固定的分支名称", rule.pattern).into());
+            }
+            rules.push((re, rule.clone()));
+        }
+        if rules.is_empty() {
+            for (pattern, tag) in DEFAULT_RULES {
+                rules.push((Regex::new(pattern)?, LayoutRule {
+                    pattern: pattern.to_string(),
+                    branch: None,
+                    tag: *tag
+                }));
+            }
+        }
+        Ok(Layout {
+            rules,
+            default_branch: config.default_branch.unwrap_or(DEFAULT_BRANCH.to_owned())
+        })
+    }
+
+    /// 默认分支
+    pub fn default_branch(&self) -> BranchRef {
+        BranchRef {
+            project: None,
+            name: self.default_branch.clone(),
+            tag: false
+        }
+    }
+
+    /// 根据路径匹配分支，使用第一个匹配的规则
+    pub fn resolve(&self, path: &str) -> Option<BranchRef> {
+        for (re, rule) in &self.rules {
+            if let Some(caps) = re.captures(path) {
+                let name = match caps.name("branch") {
+                    Some(name) if !name.as_str().is_empty() => name.as_str().to_owned(),
+                    _ => {
+                        match &rule.branch {
+                            Some(branch) => branch.clone(),
+                            None => continue
+                        }
+                    },
+                };
+                let project = caps
+                    .name("project")
+                    .map(|project| project.as_str().trim_matches('/').to_owned())
+                    .filter(|project| !project.is_empty());
+                return Some(BranchRef {
+                    project,
+                    name,
+                    tag: rule.tag
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str) -> Result<Layout, AnyError> {
+        Layout::load(&settings::SharedConfig::from_toml(toml).unwrap(), "repo")
+    }
+
+    fn branch(project: Option<&str>, name: &str, tag: bool) -> Option<BranchRef> {
+        Some(BranchRef {
+            project: project.map(str::to_owned),
+            name: name.to_owned(),
+            tag
+        })
+    }
+
+    #[test]
+    fn resolves_with_default_rules() {
+        let layout = load("").unwrap();
+        assert_eq!(layout.resolve("branches/dev/a.txt"), branch(None, "dev", false));
+        assert_eq!(layout.resolve("branch/dev"), branch(None, "dev", false));
+        //默认规则不提取项目名称，分支名称可包含点和横线
+        assert_eq!(layout.resolve("project/branches/2.1-hotfix/a.txt"), branch(None, "2.1-hotfix", false));
+        assert_eq!(layout.resolve("tags/1.0/"), branch(None, "1.0", true));
+        assert_eq!(layout.resolve("trunk/a.txt"), None);
+        assert_eq!(layout.resolve("branches/"), None);
+        assert_eq!(layout.default_branch(), BranchRef {
+            project: None,
+            name: "trunk".to_owned(),
+            tag: false
+        });
+    }
+
+    #[test]
+    fn resolves_with_repository_rules() {
+        let layout = load(
+            r#"
+[layout]
+rules = [ { pattern = "^never/(?P<branch>[^/]+)" } ]
+
+[repositories.repo.layout]
+default_branch = "main"
+rules = [
+    { pattern = "^(?P<project>[^/]+)/releases/(?P<branch>[^/]+)", tag = true },
+    { pattern = "^(?P<project>[^/]+)/branches/(?P<branch>[^/]+)" },
+    { pattern = "^(?P<project>[^/]+)/trunk(?:/|$)", branch = "trunk" }
+]
+"#
+        )
+        .unwrap();
+        assert_eq!(
+            layout.resolve("project/releases/2.1-hotfix/"),
+            branch(Some("project"), "2.1-hotfix", true)
+        );
+        assert_eq!(
+            layout.resolve("app/branches/feature-x.y/a.txt"),
+            branch(Some("app"), "feature-x.y", false)
+        );
+        //没有branch捕获组时使用固定的分支名称
+        assert_eq!(layout.resolve("lib/trunk/a.txt"), branch(Some("lib"), "trunk", false));
+        assert_eq!(layout.resolve("lib/trunk"), branch(Some("lib"), "trunk", false));
+        //仓库的规则优先于[layout]
+        assert_eq!(layout.resolve("never/dev"), None);
+        assert_eq!(layout.resolve("README.txt"), None);
+        assert_eq!(layout.default_branch().name, "main");
+
+        //其他仓库使用[layout]
+        let cfg = settings::SharedConfig::from_toml(
+            r#"layout = { rules = [ { pattern = "^dev/(?P<branch>[^/]+)" } ] }"#
+        )
+        .unwrap();
+        let other = Layout::load(&cfg, "other").unwrap();
+        assert_eq!(other.resolve("dev/1.x/a.txt"), branch(None, "1.x", false));
+        assert_eq!(other.resolve("branches/dev/a.txt"), None);
+        assert_eq!(other.default_branch().name, "trunk");
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!(load(r#"layout = { rules = [ { pattern = "^(?P<project>[^/]+)/trunk" } ] }"#).is_err());
+        assert!(load(r#"layout = { rules = [ { pattern = "^(?P<branch>[^/]+" } ] }"#).is_err());
+    }
+}
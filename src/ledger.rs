@@ -0,0 +1,94 @@
+//!
+//! 提交记录的同步台账
+//!
+//! 记录每个版本(仓库+版本号)在Worktile中的提交ID和同步进度，
+//! 已同步的版本不再重复提交，已修改状态的工作项不再重复修改，
+//! 台账只由服务进程打开(打开时压缩记录文件)，钩子和补录都通过服务的提交队列同步
+//!
+
+use super::*;
+use journal::Journal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap, sync::{Arc, Mutex}
+};
+
+/// 同步状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// 未完成(部分步骤失败)
+    Pending,
+    /// 已同步
+    Synced
+}
+
+/// 台账条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub repo: String,
+    pub rev: String,
+    pub status: Status,
+    pub progress: worktile::CommitProgress,
+    pub updated_at: chrono::NaiveDateTime
+}
+
+impl LedgerEntry {
+    pub fn new(repo: impl Into<String>, rev: impl Into<String>) -> LedgerEntry {
+        LedgerEntry {
+            repo: repo.into(),
+            rev: rev.into(),
+            status: Status::Pending,
+            progress: Default::default(),
+            updated_at: chrono::Local::now().naive_local()
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Ledger {
+    inner: Arc<Mutex<Inner>>
+}
+
+struct Inner {
+    journal: Journal<LedgerEntry>,
+    entries: HashMap<(String, String), LedgerEntry>
+}
+
+impl Ledger {
+    /// 打开台账
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Ledger, AnyError> {
+        let (mut journal, records): (Journal<LedgerEntry>, _) = Journal::open(path)?;
+        let mut entries = HashMap::new();
+        //同一版本以最后的记录为准
+        for entry in records {
+            entries.insert((entry.repo.to_owned(), entry.rev.to_owned()), entry);
+        }
+
+        //压缩记录文件
+        let mut records: Vec<LedgerEntry> = entries.values().cloned().collect();
+        records.sort_by(|a, b| a.updated_at.cmp(&b.updated_at));
+        journal.rewrite(&records)?;
+
+        Ok(Ledger {
+            inner: Arc::new(Mutex::new(Inner {
+                journal,
+                entries
+            }))
+        })
+    }
+
+    /// 查询指定版本的台账
+    pub fn get(&self, repo: &str, rev: &str) -> Option<LedgerEntry> {
+        self.inner.lock().unwrap().entries.get(&(repo.to_owned(), rev.to_owned())).cloned()
+    }
+
+    /// 更新台账
+    pub fn put(&self, mut entry: LedgerEntry) -> Result<(), AnyError> {
+        entry.updated_at = chrono::Local::now().naive_local();
+        let mut inner = self.inner.lock().unwrap();
+        inner.journal.append(&entry)?;
+        inner.entries.insert((entry.repo.to_owned(), entry.rev.to_owned()), entry);
+        Ok(())
+    }
+}
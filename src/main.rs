@@ -12,12 +12,37 @@ use std::error::Error;
 type AnyError = Box<dyn std::error::Error>;
 
 mod settings;
+mod layout;
 mod svn;
+mod git;
+mod source;
 mod worktile;
+mod cache;
+mod token;
+mod workflow;
+mod smart_commit;
+mod comment;
+mod worklog;
+mod members;
+mod journal;
+mod queue;
+mod ledger;
+mod retry;
+mod dead_letter;
 mod endpoint;
+mod backfill;
+mod precommit;
+mod service;
+#[cfg(test)]
+mod mock_worktile;
+#[cfg(test)]
+mod svn_fixture;
+#[cfg(windows)]
 mod win_service;
+#[cfg(unix)]
+mod unix_service;
 
-use win_service::WinService;
+use service::Service as _;
 
 const CLAP_TEMPLATE: &'static str = r"
 {about} [版本 {version}]
@@ -43,12 +68,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .template(CLAP_TEMPLATE)
                 .help_message("打印此帮助信息")
                 .version_message("打印版本信息")
-                .arg(Arg::with_name("install").long("install").help("安装为Windows服务").display_order(1))
-                .arg(Arg::with_name("uninstall").long("uninstall").help("从Windows服务卸载").display_order(2))
-                .arg(Arg::with_name("start").long("start").help("开始Windows服务").display_order(3))
-                .arg(Arg::with_name("stop").long("stop").help("停止Windows服务").display_order(4))
+                .arg(
+                    Arg::with_name("install")
+                        .long("install")
+                        .help("安装为系统服务(Windows服务或systemd服务)")
+                        .display_order(1)
+                )
+                .arg(Arg::with_name("uninstall").long("uninstall").help("从系统服务卸载").display_order(2))
+                .arg(Arg::with_name("start").long("start").help("开始系统服务").display_order(3))
+                .arg(Arg::with_name("stop").long("stop").help("停止系统服务").display_order(4))
                 .arg(Arg::with_name("run").long("run").help("直接运行服务").display_order(5))
-                .group(ArgGroup::with_name("action").args(&["install", "uninstall", "run", "start", "stop"]))
+                .arg(
+                    Arg::with_name("unit_file")
+                        .long("unit_file")
+                        .help("打印systemd服务配置文件(仅Linux)")
+                        .display_order(6)
+                )
+                .group(ArgGroup::with_name("action").args(&[
+                    "install",
+                    "uninstall",
+                    "run",
+                    "start",
+                    "stop",
+                    "unit_file"
+                ]))
         )
         .subcommand(
             SubCommand::with_name("commit")
@@ -85,8 +128,190 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .required(true)
                         .display_order(3)
                 )
+                .arg(Arg::with_name("force").long("force").help("强制同步已同步过的版本").display_order(4))
+        )
+        .subcommand(
+            SubCommand::with_name("precommit")
+                .version(crate_version!())
+                .author(crate_authors!())
+                .about(crate_description!())
+                .template(CLAP_TEMPLATE)
+                .help_message("打印此帮助信息")
+                .version_message("打印版本信息")
+                .arg(
+                    Arg::with_name("repo_path")
+                        .short("p")
+                        .long("repo_path")
+                        .help("仓库位置")
+                        .takes_value(true)
+                        .required(true)
+                        .display_order(1)
+                )
+                .arg(
+                    Arg::with_name("transaction")
+                        .short("t")
+                        .long("transaction")
+                        .help("事务名称")
+                        .takes_value(true)
+                        .required(true)
+                        .display_order(2)
+                )
+                .arg(
+                    Arg::with_name("repo_name")
+                        .short("n")
+                        .long("repo_name")
+                        .help("仓库名称(默认为仓库目录名称)")
+                        .takes_value(true)
+                        .display_order(3)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("receive")
+                .version(crate_version!())
+                .author(crate_authors!())
+                .about(crate_description!())
+                .template(CLAP_TEMPLATE)
+                .help_message("打印此帮助信息")
+                .version_message("打印版本信息")
+                .arg(
+                    Arg::with_name("repo_path")
+                        .short("p")
+                        .long("repo_path")
+                        .help("Git仓库位置")
+                        .takes_value(true)
+                        .required(true)
+                        .display_order(1)
+                )
+                .arg(
+                    Arg::with_name("repo_name")
+                        .short("n")
+                        .long("repo_name")
+                        .help("仓库名称")
+                        .takes_value(true)
+                        .required(true)
+                        .display_order(2)
+                )
+                .arg(Arg::with_name("force").long("force").help("强制同步已同步过的提交").display_order(3))
+        )
+        .subcommand(
+            SubCommand::with_name("backfill")
+                .version(crate_version!())
+                .author(crate_authors!())
+                .about(crate_description!())
+                .template(CLAP_TEMPLATE)
+                .help_message("打印此帮助信息")
+                .version_message("打印版本信息")
+                .arg(
+                    Arg::with_name("repo_path")
+                        .short("p")
+                        .long("repo_path")
+                        .help("仓库位置")
+                        .takes_value(true)
+                        .required(true)
+                        .display_order(1)
+                )
+                .arg(
+                    Arg::with_name("repo_name")
+                        .short("n")
+                        .long("repo_name")
+                        .help("仓库名称")
+                        .takes_value(true)
+                        .required(true)
+                        .display_order(2)
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .help("起始版本号")
+                        .takes_value(true)
+                        .required(true)
+                        .display_order(3)
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .help("结束版本号(默认为最新版本)")
+                        .takes_value(true)
+                        .display_order(4)
+                )
+                .arg(Arg::with_name("force").long("force").help("强制同步已同步过的版本").display_order(5))
+        )
+        .subcommand(
+            SubCommand::with_name("dead_letter")
+                .version(crate_version!())
+                .author(crate_authors!())
+                .about(crate_description!())
+                .template(CLAP_TEMPLATE)
+                .help_message("打印此帮助信息")
+                .version_message("打印版本信息")
+                .arg(Arg::with_name("list").long("list").help("列出同步失败的提交").display_order(1))
+                .arg(
+                    Arg::with_name("redrive")
+                        .long("redrive")
+                        .value_name("ID")
+                        .help("将指定的提交重新放入队列")
+                        .takes_value(true)
+                        .display_order(2)
+                )
+                .arg(
+                    Arg::with_name("redrive_all")
+                        .long("redrive_all")
+                        .help("将全部提交重新放入队列")
+                        .display_order(3)
+                )
+                .group(
+                    ArgGroup::with_name("action").args(&["list", "redrive", "redrive_all"]).required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("members")
+                .version(crate_version!())
+                .author(crate_authors!())
+                .about(crate_description!())
+                .template(CLAP_TEMPLATE)
+                .help_message("打印此帮助信息")
+                .version_message("打印版本信息")
+                .arg(
+                    Arg::with_name("unmapped")
+                        .long("unmapped")
+                        .help("列出未找到对应成员的提交者")
+                        .display_order(1)
+                )
+                .arg(
+                    Arg::with_name("lookup")
+                        .long("lookup")
+                        .value_name("AUTHOR")
+                        .help("查找提交者对应的Worktile成员")
+                        .takes_value(true)
+                        .display_order(2)
+                )
+                .group(ArgGroup::with_name("action").args(&["unmapped", "lookup"]).required(true))
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .version(crate_version!())
+                .author(crate_authors!())
+                .about(crate_description!())
+                .template(CLAP_TEMPLATE)
+                .help_message("打印此帮助信息")
+                .version_message("打印版本信息")
+                .arg(Arg::with_name("list").long("list").help("列出缓存的Worktile对象ID").display_order(1))
+                .arg(
+                    Arg::with_name("clear")
+                        .long("clear")
+                        .help("清空缓存的对象ID、访问令牌和成员查找结果")
+                        .display_order(2)
+                )
+                .group(ArgGroup::with_name("action").args(&["list", "clear"]).required(true))
         )
         .get_matches();
+    //命令行(钩子)和服务一样使用程序所在目录的配置文件和数据文件(调试版本使用当前目录)，
+    //参数中的路径按启动时的当前目录转换为绝对路径
+    let cwd = std::env::current_dir()?;
+    let abs_path = |path: &str| cwd.join(path).to_string_lossy().into_owned();
+    if clap.subcommand_matches("service").is_none() && !cfg!(debug_assertions) {
+        set_working_dir()?;
+    }
     //[Service]命令
     if let Some(ref matches) = clap.subcommand_matches("service") {
         if matches.is_present("install") {
@@ -99,6 +324,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Service.stop()
         } else if matches.is_present("run") {
             Service.run()
+        } else if matches.is_present("unit_file") {
+            println!("{}", Service.unit_file(vec!["service".into(), "--run".into()])?);
+            Ok(())
         } else {
             unimplemented!()
         }
@@ -107,10 +335,107 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     else if let Some(ref matches) = clap.subcommand_matches("commit") {
         match (matches.value_of("repo_path"), matches.value_of("repo_name"), matches.value_of("revision")) {
             (Some(repo_path), Some(repo_name), Some(rev)) => {
-                endpoint::request_commit(repo_path, repo_name, rev)
+                endpoint::request_commit(&endpoint::CommitParams {
+                    repo_path: abs_path(repo_path),
+                    repo_name: repo_name.to_owned(),
+                    rev: rev.to_owned(),
+                    force: matches.is_present("force"),
+                    vcs: source::Vcs::Svn,
+                    git_ref: None
+                })
             },
             _ => panic!("[commit]缺少参数")
         }
+    }
+    //[Precommit]命令
+    else if let Some(ref matches) = clap.subcommand_matches("precommit") {
+        match (matches.value_of("repo_path"), matches.value_of("transaction")) {
+            (Some(repo_path), Some(txn)) => {
+                let repo_path = abs_path(repo_path);
+                let repo_name = match matches.value_of("repo_name") {
+                    Some(repo_name) => repo_name.to_owned(),
+                    None => {
+                        std::path::Path::new(&repo_path)
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default()
+                    },
+                };
+                //检查未通过时输出到标准错误并返回非0的退出代码，SVN将拒绝提交
+                if let Err(e) = precommit::check(&repo_path, &repo_name, txn) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                Ok(())
+            },
+            _ => panic!("[precommit]缺少参数")
+        }
+    }
+    //[Receive]命令(Git post-receive钩子，从标准输入读取推送的引用)
+    else if let Some(ref matches) = clap.subcommand_matches("receive") {
+        match (matches.value_of("repo_path"), matches.value_of("repo_name")) {
+            (Some(repo_path), Some(repo_name)) => {
+                use std::io::Read;
+
+                let mut input = String::new();
+                std::io::stdin().read_to_string(&mut input)?;
+                git::post_receive(&abs_path(repo_path), repo_name, &input, matches.is_present("force"))
+            },
+            _ => panic!("[receive]缺少参数")
+        }
+    }
+    //[Backfill]命令
+    else if let Some(ref matches) = clap.subcommand_matches("backfill") {
+        let parse_rev = |name: &str| -> Result<Option<u64>, String> {
+            matches
+                .value_of(name)
+                .map(|rev| rev.parse().map_err(|_| format!("无效的版本号: {}", rev)))
+                .transpose()
+        };
+        match (matches.value_of("repo_path"), matches.value_of("repo_name"), parse_rev("from")?) {
+            (Some(repo_path), Some(repo_name), Some(from)) => {
+                backfill::run(
+                    &abs_path(repo_path),
+                    repo_name,
+                    from,
+                    parse_rev("to")?,
+                    matches.is_present("force")
+                )
+            },
+            _ => panic!("[backfill]缺少参数")
+        }
+    }
+    //[DeadLetter]命令
+    else if let Some(ref matches) = clap.subcommand_matches("dead_letter") {
+        if matches.is_present("list") {
+            endpoint::request_dead_letters()
+        } else if let Some(id) = matches.value_of("redrive") {
+            endpoint::request_redrive(Some(id.parse().map_err(|_| format!("无效的序号: {}", id))?))
+        } else if matches.is_present("redrive_all") {
+            endpoint::request_redrive(None)
+        } else {
+            unimplemented!()
+        }
+    }
+    //[Members]命令
+    else if let Some(ref matches) = clap.subcommand_matches("members") {
+        if matches.is_present("unmapped") {
+            endpoint::request_unmapped_authors()
+        } else if let Some(author) = matches.value_of("lookup") {
+            members::lookup(author)
+        } else {
+            unimplemented!()
+        }
+    }
+    //[Cache]命令
+    else if let Some(ref matches) = clap.subcommand_matches("cache") {
+        if matches.is_present("list") {
+            endpoint::request_cache()
+        } else if matches.is_present("clear") {
+            endpoint::request_clear_cache()
+        } else {
+            unimplemented!()
+        }
     } else {
         println!("{}", clap.usage());
         Ok(())
@@ -119,20 +444,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 struct Service;
 
-impl WinService for Service {
+impl service::Service for Service {
     /// 服务名称
     fn name(&self) -> &str { crate_name!() }
     /// 服务描述
     fn description(&self) -> &str { crate_description!() }
     /// 初始化
-    fn initialize(&self, from_scm: bool) -> Result<(), Box<dyn Error>> {
-        use std::{backtrace::Backtrace, env, panic};
+    fn initialize(&self, from_manager: bool) -> Result<(), Box<dyn Error>> {
+        use std::{backtrace::Backtrace, panic};
 
         //设置工作目录
-        let exe_path = env::current_exe()?;
-        if from_scm || !cfg!(debug_assertions) {
-            env::set_current_dir(exe_path.as_path().parent().unwrap())
-                .map_err(|e| format!("设置当前目录失败, {}", e))?;
+        if from_manager || !cfg!(debug_assertions) {
+            set_working_dir()?;
         }
         //初始化日志配置
         let log_de = log4rs::file::Deserializers::default();
@@ -169,6 +492,14 @@ impl WinService for Service {
     }
 }
 
+/// 设置工作目录为程序所在目录，配置文件和数据文件都相对于程序所在目录
+fn set_working_dir() -> Result<(), Box<dyn Error>> {
+    let exe_path = std::env::current_exe()?;
+    std::env::set_current_dir(exe_path.as_path().parent().unwrap())
+        .map_err(|e| format!("设置当前目录失败, {}", e))?;
+    Ok(())
+}
+
 /// 初始化默认的日志配置
 fn init_default_log(de: log4rs::file::Deserializers) -> Result<(), Box<dyn Error>> {
     use log4rs::{config::Config, file::RawConfig, Logger};
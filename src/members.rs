@@ -0,0 +1,85 @@
+//!
+//! 提交者与Worktile成员的映射
+//!
+//! 按以下顺序查找提交者对应的成员，找到第一个即停止:
+//! 1. 映射表中配置的邮箱、用户名或显示名
+//! 2. 提交者本身为邮箱时按邮箱查找
+//! 3. 配置了邮箱域名时按`用户名@域名`查找(去除`DOMAIN\`前缀)
+//! 4. 按同名的用户名或显示名查找
+//!
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 成员映射的配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MemberMapping {
+    /// 提交者 => Worktile成员的邮箱、用户名或显示名
+    pub mapping: HashMap<String, String>,
+    /// 邮箱域名，如: example.com
+    pub email_domain: String,
+    /// 是否按同名的用户名或显示名查找
+    pub match_name: bool
+}
+
+impl Default for MemberMapping {
+    fn default() -> Self {
+        MemberMapping {
+            mapping: HashMap::new(),
+            email_domain: String::new(),
+            match_name: true
+        }
+    }
+}
+
+impl MemberMapping {
+    /// 提交者对应的查找条件(邮箱、用户名或显示名)，按优先顺序排列
+    pub fn candidates(&self, author: &str) -> Vec<String> {
+        let mut candidates = Vec::new();
+        //配置文件中的键名会被转换为小写，不区分大小写比较
+        let lower = author.to_lowercase();
+        if let Some((_, target)) = self.mapping.iter().find(|(name, _)| name.to_lowercase() == lower) {
+            candidates.push(target.to_owned());
+        }
+        //去除Windows域账号的前缀
+        let login = author.rsplit('\\').next().unwrap_or(author);
+        if login.contains('@') {
+            candidates.push(login.to_owned());
+        } else if !self.email_domain.is_empty() && !login.is_empty() {
+            candidates.push(format!("{}@{}", login, self.email_domain.trim_start_matches('@')));
+        }
+        if self.match_name && !login.is_empty() {
+            candidates.push(login.to_owned());
+        }
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// 未找到对应成员的提交者
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnmappedAuthor {
+    pub name: String,
+    /// 提交的版本数量(同一版本重试或重新放入队列只计一次)
+    pub commits: u64,
+    /// 最后一次提交的版本
+    pub last_revision: String,
+    pub last_seen: chrono::NaiveDateTime
+}
+
+/// 调用本地HTTP服务打印提交者对应的成员，用于检查映射配置
+pub fn lookup(author: &str) -> Result<(), AnyError> {
+    let cfg = settings::SharedConfig::load()?;
+    let mapping: MemberMapping = cfg.config_or_default("members")?;
+
+    println!("查找条件: {}", mapping.candidates(author).join(", "));
+    match endpoint::request_member(author)? {
+        Some(member) => {
+            println!("{}\t{}\t{}\t{}", member.uid, member.name, member.display_name, member.email)
+        },
+        None => println!("未找到[{}]对应的成员", author)
+    }
+    Ok(())
+}
@@ -0,0 +1,542 @@
+//!
+//! 模拟的Worktile接口服务(测试用)
+//!
+//! 在本地端口上实现客户端使用的接口(令牌、代码托管、工作项)，
+//! 记录收到的请求用于断言，并可以注入故障(令牌失效、5xx、慢响应)
+//!
+//! ```ignore
+//! let wt = MockWorktile::start();
+//! wt.add_work_item("CD-7", "task", "新建");
+//! let client = wt.client().build()?;
+//! ```
+//!
+
+use super::*;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use serde_json as json;
+use std::{
+    collections::HashMap, net::SocketAddr, sync::{mpsc, Arc, Mutex}, thread, time::Duration
+};
+
+/// 收到的请求
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub method: String,
+    /// 路径(不含开头的`/`)，如: v1/scm/products
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub body: json::Value,
+    /// 请求使用的访问令牌
+    pub token: Option<String>
+}
+
+impl Call {
+    /// 查询参数
+    pub fn param(&self, name: &str) -> &str {
+        self.query.get(name).map(|value| value.as_str()).unwrap_or_default()
+    }
+}
+
+/// 注入的故障
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// 当前的访问令牌全部失效(返回100028)
+    ExpiredToken,
+    /// 返回指定的HTTP状态码
+    Status(u16),
+    /// 返回HTTP 200和指定的错误代码
+    Code(&'static str),
+    /// 延迟响应
+    Delay(Duration)
+}
+
+/// 模拟的对象(代码托管平台、用户、仓库、分支、标签)
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub id: String,
+    /// 所属对象的ID(如仓库所属的代码托管平台)
+    pub parent: String,
+    pub name: String,
+    pub body: json::Value
+}
+
+/// 模拟的工作项
+#[derive(Debug, Clone)]
+pub struct WorkItem {
+    pub id: String,
+    pub identifier: String,
+    pub item_type: String,
+    pub state: String,
+    pub comments: Vec<String>,
+    /// 登记的工时(小时)
+    pub workloads: Vec<json::Value>
+}
+
+/// 模拟服务的数据
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// 有效的访问令牌
+    tokens: Vec<String>,
+    /// 令牌的有效期(秒)
+    token_ttl: i64,
+    products: Vec<Object>,
+    users: Vec<Object>,
+    repositories: Vec<Object>,
+    branches: Vec<Object>,
+    tags: Vec<Object>,
+    commits: Vec<Object>,
+    refs: Vec<Object>,
+    /// 工作项状态(名称, ID)
+    states: Vec<(String, String)>,
+    work_items: Vec<WorkItem>,
+    members: Vec<json::Value>,
+    calls: Vec<Call>,
+    /// 注入的故障(方法, 路径前缀, 故障, 剩余次数)
+    faults: Vec<(String, String, Fault, u32)>
+}
+
+/// 模拟服务的响应
+struct Response {
+    status: u16,
+    body: json::Value,
+    delay: Option<Duration>
+}
+
+impl Response {
+    fn ok(body: json::Value) -> Response {
+        Response {
+            status: 200,
+            body,
+            delay: None
+        }
+    }
+
+    fn error(status: u16, code: &str, message: &str) -> Response {
+        Response {
+            status,
+            body: json::json!({ "code": code, "message": message }),
+            delay: None
+        }
+    }
+}
+
+impl State {
+    fn new_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{:08}", prefix, self.next_id)
+    }
+
+    /// 处理请求
+    fn handle(&mut self, call: Call) -> Response {
+        self.calls.push(call.clone());
+
+        //注入的故障
+        let mut delay = None;
+        if let Some(idx) = self.faults.iter().position(|(method, prefix, ..)| {
+            (method.is_empty() || *method == call.method) && call.path.starts_with(prefix.as_str())
+        }) {
+            let fault = self.faults[idx].2.clone();
+            self.faults[idx].3 -= 1;
+            if self.faults[idx].3 == 0 {
+                self.faults.remove(idx);
+            }
+            match fault {
+                Fault::ExpiredToken => self.tokens.clear(),
+                Fault::Status(status) => {
+                    return Response::error(status, &status.to_string(), "injected fault")
+                },
+                Fault::Code(code) => return Response::error(200, code, "injected fault"),
+                Fault::Delay(duration) => delay = Some(duration)
+            }
+        }
+
+        let mut resp = self.route(&call);
+        resp.delay = delay;
+        resp
+    }
+
+    fn route(&mut self, call: &Call) -> Response {
+        if call.path == "v1/auth/token" {
+            let token = self.new_id("token-");
+            self.tokens.push(token.to_owned());
+            return Response::ok(json::json!({
+                "access_token": token,
+                "expires_in": chrono::Utc::now().timestamp() + self.token_ttl
+            }));
+        }
+        match &call.token {
+            Some(token) if self.tokens.contains(token) => {},
+            _ => return Response::error(200, "100028", "'access_token'已失效")
+        }
+
+        let segs: Vec<&str> = call.path.split('/').collect();
+        let method = call.method.as_str();
+        match (method, segs.as_slice()) {
+            ("GET", ["v1", "scm", "products"]) => page(call, filter(&self.products, "", call.param("name"))),
+            ("POST", ["v1", "scm", "products"]) => self.create(call, ObjectKind::Product, ""),
+            ("GET", ["v1", "scm", "products", pid, "users"]) => {
+                page(call, filter(&self.users, pid, call.param("name")))
+            },
+            ("POST", ["v1", "scm", "products", pid, "users"]) => self.create(call, ObjectKind::User, pid),
+            ("GET", ["v1", "scm", "products", pid, "repositories"]) => {
+                page(call, filter(&self.repositories, pid, call.param("full_name")))
+            },
+            ("POST", ["v1", "scm", "products", pid, "repositories"]) => {
+                self.create(call, ObjectKind::Repository, pid)
+            },
+            ("GET", ["v1", "scm", "products", _, "repositories", rid, "branches"]) => {
+                page(call, filter(&self.branches, rid, call.param("name")))
+            },
+            ("POST", ["v1", "scm", "products", _, "repositories", rid, "branches"]) => {
+                self.create(call, ObjectKind::Branch, rid)
+            },
+            ("GET", ["v1", "scm", "products", _, "repositories", rid, "tags"]) => {
+                page(call, filter(&self.tags, rid, call.param("name")))
+            },
+            ("POST", ["v1", "scm", "products", _, "repositories", rid, "tags"]) => {
+                self.create(call, ObjectKind::Tag, rid)
+            },
+            ("POST", ["v1", "scm", "products", _, "repositories", rid, "refs"]) => {
+                self.create(call, ObjectKind::Ref, rid)
+            },
+            ("POST", ["v1", "scm", "commits"]) => self.create(call, ObjectKind::Commit, ""),
+            ("GET", ["v1", "agile", "states"]) => {
+                let states: Vec<json::Value> =
+                    self.states.iter().map(|(name, id)| json::json!({ "id": id, "name": name })).collect();
+                page(call, states)
+            },
+            ("GET", ["v1", "agile", "work_items"]) => {
+                let identifier = call.param("identifier");
+                let items: Vec<json::Value> = self
+                    .work_items
+                    .iter()
+                    .filter(|item| item.identifier.contains(identifier))
+                    .map(|item| self.work_item_json(item))
+                    .collect();
+                page(call, items)
+            },
+            ("PATCH", ["v1", "agile", _, id]) => {
+                let state_id = call.body["state_id"].as_str().unwrap_or_default().to_owned();
+                let state = match self.states.iter().find(|(_, id)| *id == state_id) {
+                    Some((name, _)) => name.to_owned(),
+                    None => return Response::error(400, "400", "invalid state_id")
+                };
+                match self.work_items.iter_mut().find(|item| item.id == *id) {
+                    Some(item) => {
+                        item.state = state;
+                        Response::ok(json::json!({ "id": id }))
+                    },
+                    None => Response::error(404, "404", "work item not found")
+                }
+            },
+            ("POST", ["v1", "agile", "work_items", id, "comments"]) => {
+                let id = id.to_string();
+                let comment_id = self.new_id("comment-");
+                match self.work_items.iter_mut().find(|item| item.id == id) {
+                    Some(item) => {
+                        item.comments.push(call.body["content"].as_str().unwrap_or_default().to_owned());
+                        Response::ok(json::json!({ "id": comment_id }))
+                    },
+                    None => Response::error(404, "404", "work item not found")
+                }
+            },
+            ("POST", ["v1", "agile", "work_items", id, "workloads"]) => {
+                let id = id.to_string();
+                let workload_id = self.new_id("workload-");
+                match self.work_items.iter_mut().find(|item| item.id == id) {
+                    Some(item) => {
+                        item.workloads.push(call.body.clone());
+                        Response::ok(json::json!({ "id": workload_id }))
+                    },
+                    None => Response::error(404, "404", "work item not found")
+                }
+            },
+            ("GET", ["v1", "directory", "users"]) => {
+                let keywords = call.param("keywords");
+                let members: Vec<json::Value> = self
+                    .members
+                    .iter()
+                    .filter(|member| {
+                        ["name", "display_name", "email"]
+                            .iter()
+                            .any(|key| member[*key].as_str().map_or(false, |value| value.contains(keywords)))
+                    })
+                    .cloned()
+                    .collect();
+                page(call, members)
+            },
+            _ => {
+                Response {
+                    status: 404,
+                    body: json::json!({ "message": "not found" }),
+                    delay: None
+                }
+            },
+        }
+    }
+
+    /// 创建对象，返回新对象的ID
+    fn create(&mut self, call: &Call, kind: ObjectKind, parent: &str) -> Response {
+        let id = self.new_id(kind.prefix());
+        let name = call.body["full_name"].as_str().or(call.body["name"].as_str()).unwrap_or_default();
+        let object = Object {
+            id: id.to_owned(),
+            parent: parent.to_owned(),
+            name: name.to_owned(),
+            body: call.body.clone()
+        };
+        self.objects(kind).push(object);
+        Response::ok(json::json!({ "id": id }))
+    }
+
+    fn objects(&mut self, kind: ObjectKind) -> &mut Vec<Object> {
+        match kind {
+            ObjectKind::Product => &mut self.products,
+            ObjectKind::User => &mut self.users,
+            ObjectKind::Repository => &mut self.repositories,
+            ObjectKind::Branch => &mut self.branches,
+            ObjectKind::Tag => &mut self.tags,
+            ObjectKind::Commit => &mut self.commits,
+            ObjectKind::Ref => &mut self.refs
+        }
+    }
+
+    fn work_item_json(&self, item: &WorkItem) -> json::Value {
+        let state_id = self.states.iter().find(|(name, _)| *name == item.state).map(|(_, id)| id.as_str());
+        json::json!({
+            "id": item.id,
+            "identifier": item.identifier,
+            "type": item.item_type,
+            "state": { "id": state_id.unwrap_or_default(), "name": item.state }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ObjectKind {
+    Product,
+    User,
+    Repository,
+    Branch,
+    Tag,
+    Commit,
+    Ref
+}
+
+impl ObjectKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            ObjectKind::Product => "product-",
+            ObjectKind::User => "user-",
+            ObjectKind::Repository => "repo-",
+            ObjectKind::Branch => "branch-",
+            ObjectKind::Tag => "tag-",
+            ObjectKind::Commit => "commit-",
+            ObjectKind::Ref => "ref-"
+        }
+    }
+}
+
+/// 按所属对象和名称过滤(与Worktile一致，名称为模糊匹配)
+fn filter(objects: &[Object], parent: &str, name: &str) -> Vec<json::Value> {
+    objects
+        .iter()
+        .filter(|object| object.parent == parent && object.name.contains(name))
+        .map(|object| {
+            json::json!({
+                "id": object.id,
+                "name": object.body["name"],
+                "full_name": object.body["full_name"]
+            })
+        })
+        .collect()
+}
+
+/// 按`page_index`和`page_size`分页
+fn page(call: &Call, values: Vec<json::Value>) -> Response {
+    let page_index: usize = call.param("page_index").parse().unwrap_or(0);
+    let page_size: usize = call.param("page_size").parse().unwrap_or(20);
+    let total = values.len();
+    let values: Vec<json::Value> = values.into_iter().skip(page_index * page_size).take(page_size).collect();
+    Response::ok(json::json!({
+        "values": values,
+        "page_index": page_index,
+        "page_size": page_size,
+        "total": total
+    }))
+}
+
+/// 模拟的Worktile接口服务，Drop时停止
+pub struct MockWorktile {
+    addr: SocketAddr,
+    server: actix_web::dev::Server,
+    state: Arc<Mutex<State>>
+}
+
+impl MockWorktile {
+    /// 在随机端口上启动服务
+    pub fn start() -> MockWorktile {
+        let state = Arc::new(Mutex::new(State {
+            token_ttl: 7 * 24 * 3600,
+            ..Default::default()
+        }));
+        let (tx, rx) = mpsc::channel();
+        {
+            let state = state.clone();
+            thread::spawn(move || {
+                let system = actix_rt::System::new("mock-worktile");
+                let srv = HttpServer::new(move || {
+                    App::new().data(state.clone()).default_service(web::route().to(dispatch))
+                })
+                .workers(1)
+                .bind("127.0.0.1:0")
+                .unwrap();
+                let addr = srv.addrs()[0];
+                let _ = tx.send((addr, srv.run()));
+                system.run()
+            });
+        }
+        let (addr, server) = rx.recv().unwrap();
+        MockWorktile {
+            addr,
+            server,
+            state
+        }
+    }
+
+    /// 服务地址
+    pub fn url(&self) -> String { format!("http://{}", self.addr) }
+
+    /// 使用模拟服务的客户端
+    pub fn client(&self) -> worktile::ClientBuilder {
+        worktile::Client::build().api_url(self.url()).product_name("SVN").credential("id", "key")
+    }
+
+    /// 添加工作项状态
+    pub fn add_state(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        let id = state.new_id("state-");
+        state.states.push((name.to_owned(), id));
+    }
+
+    /// 添加工作项(状态不存在时同时添加)
+    pub fn add_work_item(&self, identifier: &str, item_type: &str, item_state: &str) {
+        if !self.state.lock().unwrap().states.iter().any(|(name, _)| name == item_state) {
+            self.add_state(item_state);
+        }
+        let mut state = self.state.lock().unwrap();
+        let id = state.new_id("item-");
+        state.work_items.push(WorkItem {
+            id,
+            identifier: identifier.to_owned(),
+            item_type: item_type.to_owned(),
+            state: item_state.to_owned(),
+            comments: Vec::new(),
+            workloads: Vec::new()
+        });
+    }
+
+    /// 添加企业成员
+    pub fn add_member(&self, name: &str, display_name: &str, email: &str) {
+        let mut state = self.state.lock().unwrap();
+        let uid = state.new_id("member-");
+        state.members.push(json::json!({
+            "uid": uid,
+            "name": name,
+            "display_name": display_name,
+            "email": email
+        }));
+    }
+
+    /// 添加代码仓库，返回仓库ID
+    pub fn add_repository(&self, product_id: &str, full_name: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = state.new_id("repo-");
+        state.repositories.push(Object {
+            id: id.to_owned(),
+            parent: product_id.to_owned(),
+            name: full_name.to_owned(),
+            body: json::json!({ "name": full_name, "full_name": full_name })
+        });
+        id
+    }
+
+    /// 添加代码托管平台，返回ID
+    pub fn add_product(&self, name: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = state.new_id("product-");
+        state.products.push(Object {
+            id: id.to_owned(),
+            parent: String::new(),
+            name: name.to_owned(),
+            body: json::json!({ "name": name })
+        });
+        id
+    }
+
+    /// 设置新令牌的有效期(秒)
+    pub fn token_ttl(&self, secs: i64) { self.state.lock().unwrap().token_ttl = secs; }
+
+    /// 注入故障，`method`为空时匹配全部方法，对路径以`path`开头的请求生效`times`次
+    pub fn fault(&self, method: &str, path: &str, fault: Fault, times: u32) {
+        self.state.lock().unwrap().faults.push((method.to_owned(), path.to_owned(), fault, times));
+    }
+
+    /// 收到的全部请求
+    pub fn calls(&self) -> Vec<Call> { self.state.lock().unwrap().calls.clone() }
+
+    /// 收到的指定请求，`path`为路径前缀
+    pub fn calls_to(&self, method: &str, path: &str) -> Vec<Call> {
+        self.calls().into_iter().filter(|call| call.method == method && call.path.starts_with(path)).collect()
+    }
+
+    /// 清空收到的请求记录
+    pub fn clear_calls(&self) { self.state.lock().unwrap().calls.clear(); }
+
+    /// 查询工作项
+    pub fn work_item(&self, identifier: &str) -> Option<WorkItem> {
+        self.state.lock().unwrap().work_items.iter().find(|item| item.identifier == identifier).cloned()
+    }
+
+    /// 创建的对象: `products`, `users`, `repositories`, `branches`, `tags`, `commits`, `refs`
+    pub fn objects(&self, kind: &str) -> Vec<Object> {
+        let kind = match kind {
+            "products" => ObjectKind::Product,
+            "users" => ObjectKind::User,
+            "repositories" => ObjectKind::Repository,
+            "branches" => ObjectKind::Branch,
+            "tags" => ObjectKind::Tag,
+            "commits" => ObjectKind::Commit,
+            "refs" => ObjectKind::Ref,
+            unknown => panic!("unknown object kind: {}", unknown)
+        };
+        self.state.lock().unwrap().objects(kind).clone()
+    }
+}
+
+impl Drop for MockWorktile {
+    fn drop(&mut self) { let _ = self.server.stop(false); }
+}
+
+/// 将HTTP请求转换为模拟服务的请求
+async fn dispatch(req: HttpRequest, body: web::Bytes, state: web::Data<Arc<Mutex<State>>>) -> HttpResponse {
+    let call = Call {
+        method: req.method().as_str().to_owned(),
+        path: req.path().trim_start_matches('/').to_owned(),
+        query: web::Query::<HashMap<String, String>>::from_query(req.query_string())
+            .map(|query| query.into_inner())
+            .unwrap_or_default(),
+        body: json::from_slice(&body).unwrap_or(json::Value::Null),
+        token: req
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim_start_matches("Bearer ").to_owned())
+    };
+    let resp = state.lock().unwrap().handle(call);
+    if let Some(delay) = resp.delay {
+        tokio::time::delay_for(delay).await;
+    }
+    HttpResponse::build(actix_web::http::StatusCode::from_u16(resp.status).unwrap()).json(resp.body)
+}
@@ -0,0 +1,144 @@
+//!
+//! SVN提交前检查(pre-commit钩子)
+//!
+//! 检查提交日志是否关联了Worktile工作项，不满足规则时返回错误，由SVN拒绝提交
+//!
+
+use super::*;
+use regex::Regex;
+use serde::Deserialize;
+
+/// 提交前检查的规则
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PrecommitRules {
+    /// 是否要求关联工作项
+    pub required: bool,
+    /// 是否检查工作项在Worktile中存在
+    pub verify: bool,
+    /// 检查工作项时Worktile不可用(网络、服务、认证等错误)是否放行
+    pub fail_open: bool,
+    /// 需要检查的分支(正则表达式)，为空时检查全部分支
+    pub branches: Vec<String>,
+    /// 提交日志包含任意一项时不检查(不区分大小写)
+    pub exemptions: Vec<String>,
+    /// 不检查的作者
+    pub exempt_authors: Vec<String>
+}
+
+impl Default for PrecommitRules {
+    fn default() -> Self {
+        PrecommitRules {
+            required: true,
+            verify: false,
+            fail_open: true,
+            branches: Vec::new(),
+            exemptions: vec!["[skip wt]".to_owned()],
+            exempt_authors: Vec::new()
+        }
+    }
+}
+
+/// 转换为提交者可以理解的错误信息
+pub fn user_message(e: AnyError) -> AnyError {
+    use worktile::ErrorCategory;
+
+    let category = match e.downcast_ref::<worktile::ApiError>() {
+        Some(api_error) => api_error.category(),
+        None => return e
+    };
+    let message = match category {
+        ErrorCategory::Auth => "Worktile认证失败，请联系管理员检查client_id和client_secret",
+        ErrorCategory::Forbidden => "没有查询Worktile工作项的权限，请联系管理员",
+        ErrorCategory::RateLimited => "Worktile请求过于频繁，请稍后重新提交",
+        ErrorCategory::Network | ErrorCategory::Server => "Worktile服务暂时不可用，请稍后重新提交",
+        _ => return e
+    };
+    format!("{}: {}", message, e).into()
+}
+
+/// 检查事务的提交日志
+/// 未通过检查时返回的错误信息会显示给提交者
+pub fn check(repo_path: &str, repo_name: &str, txn: &str) -> Result<(), AnyError> {
+    let cfg = settings::SharedConfig::load()?;
+    let rules: PrecommitRules = cfg.config_or_default("precommit")?;
+    let worklog: worklog::WorklogConfig = cfg.config_or_default("worklog")?;
+    let branches = rules
+        .branches
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(|e| format!("分支规则[{}]无效: {}", pattern, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let layout = layout::Layout::load(&cfg, repo_name)?;
+    let repo = svn::open(&cfg, repo_path)?;
+    //通过本地服务查询工作项，不在钩子进程中打开缓存文件
+    let client = if rules.verify {
+        Some(reqwest::Client::builder().no_proxy().build()?)
+    } else {
+        None
+    };
+
+    let txn = txn.to_owned();
+    let mut system = actix_rt::System::new("precommit");
+    system.block_on(async move {
+        let txn = repo.transaction(&txn).await?;
+        let commands = smart_commit::parse(&txn.message);
+
+        //登记的工时超过上限时拒绝提交
+        if worklog.enabled {
+            worklog.collect(&commands)?;
+        }
+        if !rules.required {
+            return Ok(());
+        }
+
+        //豁免的提交
+        let message = txn.message.to_lowercase();
+        if rules.exemptions.iter().any(|exemption| message.contains(&exemption.to_lowercase())) ||
+            rules.exempt_authors.contains(&txn.author)
+        {
+            return Ok(());
+        }
+
+        //只检查指定的分支
+        if !branches.is_empty() &&
+            !txn
+                .changed
+                .split(&layout)
+                .iter()
+                .any(|changed| branches.iter().any(|re| re.is_match(&changed.branch.name)))
+        {
+            return Ok(());
+        }
+
+        let identifiers = commands.identifiers();
+        if identifiers.is_empty() {
+            return Err(format!(
+                "提交日志中没有关联Worktile工作项，请使用 #编号 (完成工作项) 或 @编号 (关联工作项)，如: #CD-7{}",
+                rules.exemptions.first().map(|exemption| format!("，或添加{}跳过检查", exemption)).unwrap_or_default()
+            )
+            .into());
+        }
+
+        //检查工作项是否存在，只有查询成功且没有找到时才认为不存在
+        if let Some(client) = client {
+            let mut missing = Vec::new();
+            for identifier in identifiers {
+                match endpoint::request_work_item_exists(&client, &identifier).await {
+                    Ok(true) => {},
+                    Ok(false) => missing.push(identifier),
+                    //本地服务或Worktile不可用时不阻止提交
+                    Err(e) if rules.fail_open => {
+                        eprintln!("警告: 无法检查Worktile工作项，已跳过检查: {}", e);
+                        break;
+                    },
+                    Err(e) => return Err(e)
+                }
+            }
+            if !missing.is_empty() {
+                return Err(format!("Worktile工作项不存在: {}", missing.join(", ")).into());
+            }
+        }
+
+        Ok::<_, AnyError>(())
+    })
+}
@@ -0,0 +1,181 @@
+//!
+//! 持久化的代码提交队列
+//!
+//! 提交请求先写入记录文件再答复，由后台任务按顺序同步到Worktile，
+//! 同步成功后才标记完成，服务重启时重新处理未完成的请求，
+//! 按重试策略重试后仍失败的请求移入死信存储
+//!
+
+use super::*;
+use endpoint::CommitParams;
+use journal::Journal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque}, sync::{Arc, Mutex}, time::Duration
+};
+use tokio::sync::Notify;
+
+/// 写入死信存储失败后重新处理的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 队列中的提交任务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub params: CommitParams,
+    pub accepted_at: chrono::NaiveDateTime
+}
+
+/// 记录文件的条目
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    /// 压缩时保存的序号
+    Checkpoint {
+        next_id: u64
+    },
+    /// 新增任务
+    Push(Entry),
+    /// 任务完成
+    Done {
+        id: u64
+    }
+}
+
+#[derive(Clone)]
+pub struct CommitQueue {
+    inner: Arc<Mutex<Inner>>,
+    notify: Arc<Notify>
+}
+
+struct Inner {
+    journal: Journal<Record>,
+    /// 下一个任务的序号
+    next_id: u64,
+    /// 未完成的任务
+    pending: VecDeque<Entry>
+}
+
+impl CommitQueue {
+    /// 打开队列，加载未完成的任务
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<CommitQueue, AnyError> {
+        let (mut journal, records) = Journal::open(path)?;
+        let mut next_id = 1;
+        let mut pending = BTreeMap::new();
+        for record in records {
+            match record {
+                Record::Checkpoint {
+                    next_id: id
+                } => next_id = next_id.max(id),
+                Record::Push(entry) => {
+                    next_id = next_id.max(entry.id + 1);
+                    pending.insert(entry.id, entry);
+                },
+                Record::Done {
+                    id
+                } => {
+                    pending.remove(&id);
+                }
+            }
+        }
+
+        //压缩记录文件，只保留未完成的任务
+        let mut records = vec![Record::Checkpoint {
+            next_id
+        }];
+        records.extend(pending.values().cloned().map(Record::Push));
+        journal.rewrite(&records)?;
+
+        if !pending.is_empty() {
+            info!("commit queue: {} pending entries restored", pending.len());
+        }
+
+        Ok(CommitQueue {
+            inner: Arc::new(Mutex::new(Inner {
+                journal,
+                next_id,
+                pending: pending.into_iter().map(|(_, entry)| entry).collect()
+            })),
+            notify: Arc::new(Notify::new())
+        })
+    }
+
+    /// 添加任务，写入记录文件后返回任务序号
+    pub fn push(&self, params: CommitParams) -> Result<u64, AnyError> {
+        let id = {
+            let mut inner = self.inner.lock().unwrap();
+            let entry = Entry {
+                id: inner.next_id,
+                params,
+                accepted_at: chrono::Local::now().naive_local()
+            };
+            inner.journal.append(&Record::Push(entry.clone()))?;
+            inner.next_id += 1;
+            inner.pending.push_back(entry);
+            inner.next_id - 1
+        };
+        self.notify.notify();
+        Ok(id)
+    }
+
+    /// 标记任务完成
+    pub fn done(&self, id: u64) -> Result<(), AnyError> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.journal.append(&Record::Done {
+            id
+        })?;
+        inner.pending.retain(|entry| entry.id != id);
+        Ok(())
+    }
+
+    /// 未完成的任务数量
+    pub fn len(&self) -> usize { self.inner.lock().unwrap().pending.len() }
+
+    /// 等待并获取队首的任务(不移除)
+    pub async fn next(&self) -> Entry {
+        loop {
+            if let Some(entry) = self.inner.lock().unwrap().pending.front() {
+                return entry.clone();
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// 后台任务，按顺序将队列中的提交同步到Worktile
+pub async fn worker(
+    queue: CommitQueue,
+    dead_letters: dead_letter::DeadLetters,
+    ctx: endpoint::SyncContext,
+    policy: retry::RetryPolicy
+) {
+    loop {
+        let entry = queue.next().await;
+        let params = &entry.params;
+        match retry::retry(&policy, || endpoint::sync_commit(&ctx, params)).await {
+            Ok(_) => {
+                info!("commit #{} {} synced", entry.id, params);
+            },
+            Err(e) => {
+                error!(
+                    "commit #{} {} sync failed after {} attempts: {}",
+                    entry.id, params, e.attempts, e.error
+                );
+                //移入死信存储
+                if let Err(err) = dead_letters.put(entry.clone(), &e.error, e.attempts) {
+                    error!(
+                        "commit #{} move to dead letters failed: {}, retry after {}s",
+                        entry.id,
+                        err,
+                        RETRY_INTERVAL.as_secs()
+                    );
+                    tokio::time::delay_for(RETRY_INTERVAL).await;
+                    continue;
+                }
+            }
+        }
+        if let Err(e) = queue.done(entry.id) {
+            error!("commit #{} mark done failed: {}", entry.id, e);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//!
+//! 失败重试策略
+//!
+
+use super::{
+    worktile::{ApiError, ErrorCategory}, AnyError
+};
+use serde::Deserialize;
+use std::{future::Future, time::Duration};
+
+/// 可重试的错误类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// 网络错误(连接失败、超时等)
+    Network,
+    /// HTTP 5xx错误
+    Server,
+    /// Worktile接口返回的错误代码(需要在`retry_codes`中指定)
+    ApiCode,
+    /// 请求过于频繁(HTTP 429)
+    RateLimited,
+    /// 缓存的ID已不存在(重试时重新查询)
+    Stale,
+    /// 认证失败
+    Auth,
+    /// 其它错误
+    Other
+}
+
+impl ErrorKind {
+    /// 判断错误类型
+    pub fn of(e: &AnyError) -> ErrorKind {
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() {
+                return ErrorKind::Network;
+            }
+            if e.status().map(|status| status.is_server_error()).unwrap_or(false) {
+                return ErrorKind::Server;
+            }
+        }
+        let e = match e.downcast_ref::<ApiError>() {
+            Some(e) => e,
+            None => return ErrorKind::Other
+        };
+        match (e, e.category()) {
+            (
+                ApiError::Stale {
+                    ..
+                },
+                _
+            ) => ErrorKind::Stale,
+            (_, ErrorCategory::Network) => ErrorKind::Network,
+            (_, ErrorCategory::Server) => ErrorKind::Server,
+            (_, ErrorCategory::RateLimited) => ErrorKind::RateLimited,
+            (_, ErrorCategory::Auth) => ErrorKind::Auth,
+            (
+                ApiError::Code {
+                    ..
+                },
+                _
+            ) => ErrorKind::ApiCode,
+            _ => ErrorKind::Other
+        }
+    }
+}
+
+/// 重试策略
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// 最大尝试次数(包括第一次)
+    pub attempts: u32,
+    /// 第一次重试前的等待时间(秒)
+    pub backoff: u64,
+    /// 等待时间的增长倍数
+    pub multiplier: f64,
+    /// 最大等待时间(秒)
+    pub max_backoff: u64,
+    /// 等待时间的随机抖动比例(0~1)
+    pub jitter: f64,
+    /// 可重试的错误类型
+    pub retry_on: Vec<ErrorKind>,
+    /// 可重试的Worktile错误代码
+    pub retry_codes: Vec<String>
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 5,
+            backoff: 5,
+            multiplier: 2.0,
+            max_backoff: 600,
+            jitter: 0.2,
+            retry_on: vec![ErrorKind::Network, ErrorKind::Server, ErrorKind::RateLimited, ErrorKind::Stale],
+            retry_codes: vec![]
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 判断错误是否可以重试
+    pub fn is_retryable(&self, e: &AnyError) -> bool {
+        let kind = ErrorKind::of(e);
+        if !self.retry_on.contains(&kind) {
+            return false;
+        }
+        match e.downcast_ref::<ApiError>().and_then(|e| e.code()) {
+            Some(code) if kind == ErrorKind::ApiCode => self.retry_codes.iter().any(|retry| retry == code),
+            _ => true
+        }
+    }
+
+    /// 第N次失败后的等待时间
+    pub fn delay(&self, attempt: u32) -> Duration {
+        use rand::{thread_rng, Rng};
+
+        let secs = (self.backoff as f64 * self.multiplier.max(1.0).powi(attempt as i32 - 1))
+            .min(self.max_backoff as f64);
+        let jitter = self.jitter.max(0.0).min(1.0);
+        let secs = if jitter > 0.0 {
+            secs * thread_rng().gen_range(1.0 - jitter, 1.0 + jitter)
+        } else {
+            secs
+        };
+        Duration::from_millis((secs * 1000.0) as u64)
+    }
+}
+
+/// 重试次数用尽或不可重试的错误
+pub struct RetryError {
+    pub error: AnyError,
+    /// 已尝试的次数
+    pub attempts: u32
+}
+
+/// 按重试策略执行
+pub async fn retry<F, Fut, T>(policy: &RetryPolicy, mut f: F) -> Result<T, RetryError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AnyError>>
+{
+    let mut attempts = 1;
+    loop {
+        match f().await {
+            Ok(rv) => return Ok(rv),
+            Err(error) => {
+                if attempts >= policy.attempts || !policy.is_retryable(&error) {
+                    return Err(RetryError {
+                        error,
+                        attempts
+                    });
+                }
+                let delay = policy.delay(attempts);
+                warn!("attempt {} failed: {}, retry after {}ms", attempts, error, delay.as_millis());
+                tokio::time::delay_for(delay).await;
+                attempts += 1;
+            }
+        }
+    }
+}
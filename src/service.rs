@@ -0,0 +1,64 @@
+//!
+//! 服务接口封装
+//!
+//! 实现Service接口即可，Windows下由SCM托管，Linux下由systemd托管
+//!
+
+use futures::channel::oneshot;
+use std::{error::Error, ffi::OsString};
+
+#[cfg(unix)]
+use super::unix_service as platform;
+#[cfg(windows)]
+use super::win_service as platform;
+
+/// 退出代码
+pub mod exit_code {
+    pub const OK: u16 = 0;
+    pub const RESTART: u16 = 3010; //ERROR_SUCCESS_REBOOT_REQUIRED
+}
+
+/// 服务接口
+pub trait Service: Sync {
+    /// 服务名称
+    fn name(&self) -> &str;
+
+    /// 服务描述
+    fn description(&self) -> &str { self.name() }
+
+    /// 初始化
+    /// `from_manager`为真时表示由服务管理器(SCM或systemd)启动
+    fn initialize(&self, from_manager: bool) -> Result<(), Box<dyn Error>>;
+    /// 服务入口过程
+    fn main(&self, stop_signer: Option<oneshot::Receiver<()>>) -> Result<u16, Box<dyn Error>>;
+
+    /// 安装服务
+    fn install(&self, run_args: Vec<OsString>) -> Result<(), Box<dyn Error>> {
+        platform::install(self.name(), self.description(), run_args)
+    }
+
+    /// 卸载服务
+    fn uninstall(&self) -> Result<(), Box<dyn Error>> { platform::uninstall(self.name()) }
+
+    /// 运行服务
+    fn run(&self) -> Result<(), Box<dyn Error>>
+    where
+        Self: Sized
+    {
+        platform::run(self)
+    }
+
+    /// 启动服务
+    fn start(&self) -> Result<(), Box<dyn Error>> { platform::start(self.name()) }
+
+    /// 停止服务
+    fn stop(&self) -> Result<(), Box<dyn Error>> { platform::stop(self.name()) }
+
+    /// 生成systemd服务配置文件
+    fn unit_file(&self, run_args: Vec<OsString>) -> Result<String, Box<dyn Error>> {
+        platform::unit_file(self.description(), run_args)
+    }
+}
+
+/// 通知服务管理器服务已就绪
+pub fn notify_ready() { platform::notify_ready() }
@@ -5,7 +5,7 @@ use std::sync::{Arc, RwLock};
 /// 静态数据
 pub mod data {
     /// 配置参数
-    pub const CONFIG_STR: &'static str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "\\log.toml"));
+    pub const CONFIG_STR: &'static str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/log.toml"));
 }
 
 /// 默认参数
@@ -18,6 +18,20 @@ pub mod default {
     pub const HTTP_LISTEN: &'static str = "127.0.0.1:1086";
     /// HTTP日志格式
     pub const HTTP_LOG_FORMAT: &'static str = "src: %a req: \"%r\", %{Content-Type}i resp: %s, %bbytes, %{Content-Encoding}o agent: \"%{User-Agent}i\" elapsed: %Dms";
+    /// 提交队列的记录文件相对路径
+    pub const QUEUE_PATH: &'static str = "data/queue.journal";
+    /// 死信存储的记录文件相对路径
+    pub const DEAD_LETTER_PATH: &'static str = "data/dead_letter.journal";
+    /// 同步台账的记录文件相对路径
+    pub const LEDGER_PATH: &'static str = "data/ledger.journal";
+    /// Worktile对象ID缓存的记录文件相对路径
+    pub const WORKTILE_CACHE_PATH: &'static str = "data/worktile_cache.journal";
+    /// 补录进度的保存目录
+    pub const BACKFILL_PATH: &'static str = "data/backfill";
+    /// svnlook输出的文本编码
+    pub const SVN_ENCODING: &'static str = "auto";
+    /// 运行svnlook使用的区域设置(编码为UTF-8时)
+    pub const SVN_LOCALE: &'static str = "C.UTF-8";
 }
 
 #[derive(Clone)]
@@ -28,10 +42,7 @@ pub struct SharedConfig {
 
 impl SharedConfig {
     pub fn load() -> Result<SharedConfig, ConfigError> {
-        let mut cfg = Config::new();
-        //配置默认参数
-        cfg.set_default("http.listen", default::HTTP_LISTEN)?;
-        cfg.set_default("http.log_format", default::HTTP_LOG_FORMAT)?;
+        let mut cfg = SharedConfig::defaults()?;
         //加载配置文件合并参数
         cfg.merge(File::with_name(default::CONFIG_PATH).required(false))?;
 
@@ -42,6 +53,31 @@ impl SharedConfig {
         })
     }
 
+    /// 使用TOML文本代替配置文件加载参数(测试用)
+    #[cfg(test)]
+    pub fn from_toml(toml: &str) -> Result<SharedConfig, ConfigError> {
+        let mut cfg = SharedConfig::defaults()?;
+        cfg.merge(File::from_str(toml, config::FileFormat::Toml))?;
+        Ok(SharedConfig {
+            cfg: Arc::new(RwLock::new(cfg))
+        })
+    }
+
+    /// 配置默认参数
+    fn defaults() -> Result<Config, ConfigError> {
+        let mut cfg = Config::new();
+        cfg.set_default("http.listen", default::HTTP_LISTEN)?;
+        cfg.set_default("http.log_format", default::HTTP_LOG_FORMAT)?;
+        cfg.set_default("queue.path", default::QUEUE_PATH)?;
+        cfg.set_default("queue.dead_letter_path", default::DEAD_LETTER_PATH)?;
+        cfg.set_default("ledger.path", default::LEDGER_PATH)?;
+        cfg.set_default("backfill.path", default::BACKFILL_PATH)?;
+        cfg.set_default("worktile.cache_path", default::WORKTILE_CACHE_PATH)?;
+        cfg.set_default("svn.encoding", default::SVN_ENCODING)?;
+        cfg.set_default("svn.locale", default::SVN_LOCALE)?;
+        Ok(cfg)
+    }
+
     pub fn config_string(&self, key: &str) -> String {
         self.cfg.read().unwrap().get_str(key).expect(&format!("config key: {}, type: string", key))
     }
@@ -57,4 +93,14 @@ impl SharedConfig {
     pub fn config<'de, T: serde::Deserialize<'de>>(&self, key: &str) -> Result<T, ConfigError> {
         self.cfg.read().unwrap().get(key)
     }
+    /// 未配置时返回默认值
+    pub fn config_or_default<'de, T: serde::Deserialize<'de> + Default>(
+        &self,
+        key: &str
+    ) -> Result<T, ConfigError> {
+        match self.config(key) {
+            Err(ConfigError::NotFound(_)) => Ok(T::default()),
+            rv => rv
+        }
+    }
 }
@@ -0,0 +1,497 @@
+//!
+//! 提交日志中的工作项命令(Smart Commits)
+//!
+//! 支持的写法:
+//! ```text
+//! #CD-7              修改状态(默认关键字)
+//! @CD-7              仅关联
+//! #CD-7,CD-8         多个工作项
+//! #CD-7..CD-9        范围(等同于CD-7,CD-8,CD-9，也可以写为CD-7..9)
+//! #fix CD-7          按关键字修改状态
+//! CD-7 #review       按关键字修改状态
+//! CD-7 #comment 内容  添加评论(到行尾)
+//! CD-7 #time 2h30m 内容  登记工时(w/d/h/m，1w=5d，1d=8h)
+//! CD-7 工时:2h 内容   登记工时(同#time)
+//! #time 1h30m 内容    登记工时(未指定工作项时登记到提交日志中的全部工作项)
+//! fixes CD-7         修改状态(默认关键字)，支持fix(es/ed)、close(s/d)、resolve(s/d)
+//! ```
+//!
+
+use regex::Regex;
+
+/// 范围展开的最大数量
+const MAX_RANGE: u64 = 100;
+
+/// 每天的工时(分钟)
+const MINUTES_PER_DAY: u64 = 8 * 60;
+/// 每周的工作日
+const DAYS_PER_WEEK: u64 = 5;
+
+/// 工作项命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// 仅关联
+    Relate {
+        id: String
+    },
+    /// 修改状态，关键字为空时使用默认关键字
+    Transition {
+        id: String,
+        keyword: String
+    },
+    /// 添加评论
+    Comment {
+        id: String,
+        text: String
+    },
+    /// 登记工时
+    Time {
+        id: String,
+        minutes: u64,
+        comment: String
+    }
+}
+
+impl Command {
+    /// 工作项编号
+    pub fn id(&self) -> &str {
+        match self {
+            Command::Relate {
+                id
+            } |
+            Command::Transition {
+                id,
+                ..
+            } |
+            Command::Comment {
+                id,
+                ..
+            } |
+            Command::Time {
+                id,
+                ..
+            } => id
+        }
+    }
+}
+
+/// 提交日志中的命令列表
+#[derive(Debug, Default)]
+pub struct Commands {
+    pub commands: Vec<Command>
+}
+
+impl Commands {
+    /// 全部工作项编号(去除重复)
+    pub fn identifiers(&self) -> Vec<String> {
+        let mut identifiers: Vec<String> = Vec::new();
+        for command in &self.commands {
+            if !identifiers.iter().any(|id| id == command.id()) {
+                identifiers.push(command.id().to_owned());
+            }
+        }
+        identifiers
+    }
+
+    /// 修改状态的命令(关键字, 编号)
+    pub fn transitions(&self) -> Vec<(&str, &str)> {
+        self.commands
+            .iter()
+            .filter_map(|command| {
+                match command {
+                    Command::Transition {
+                        id,
+                        keyword
+                    } => Some((keyword.as_str(), id.as_str())),
+                    _ => None
+                }
+            })
+            .collect()
+    }
+
+    /// 登记工时的命令(编号, 分钟, 内容)
+    pub fn worklogs(&self) -> Vec<(&str, u64, &str)> {
+        self.commands
+            .iter()
+            .filter_map(|command| {
+                match command {
+                    Command::Time {
+                        id,
+                        minutes,
+                        comment
+                    } => Some((id.as_str(), *minutes, comment.as_str())),
+                    _ => None
+                }
+            })
+            .collect()
+    }
+}
+
+/// 解析提交日志
+pub fn parse(message: &str) -> Commands {
+    const ID: &'static str = r"[A-Za-z][A-Za-z0-9_]*-[0-9]+";
+    let list = format!(r"{id}(?:\.\.(?:{id}|[0-9]+))?(?:,{id}(?:\.\.(?:{id}|[0-9]+))?)*", id = ID);
+    let re = Regex::new(&format!(
+        r"(?P<close>\b(?i:fix|fixes|fixed|close|closes|closed|resolve|resolves|resolved):?\s+)(?P<close_ids>{list})|(?P<mark>[#@])(?P<mark_ids>{list})|#(?P<cmd>[A-Za-z]+)|(?P<worklog>工时\s*[:：])|(?P<ids>{list})",
+        list = list
+    ))
+    .unwrap();
+
+    let mut commands = Vec::new();
+    //未指定工作项的工时
+    let mut worklogs: Vec<(u64, String)> = Vec::new();
+    for line in message.lines() {
+        //等待命令的工作项(如`CD-7 #comment`)
+        let mut pending: Vec<String> = Vec::new();
+        //等待工作项的关键字(如`#fix CD-7`)
+        let mut pending_keyword: Option<String> = None;
+        let mut last_end = 0;
+        for caps in re.captures_iter(line) {
+            let m = caps.get(0).unwrap();
+            //与上一项之间有其它内容时不再关联
+            if !line[last_end..m.start()].trim_matches(|c: char| c.is_whitespace() || c == ',').is_empty() {
+                pending.clear();
+                pending_keyword = None;
+            }
+            last_end = m.end();
+
+            if let Some(ids) = caps.name("close_ids") {
+                if !is_boundary(line, ids.start(), ids.end()) {
+                    continue;
+                }
+                for id in expand_ids(ids.as_str()) {
+                    commands.push(Command::Transition {
+                        id,
+                        keyword: String::new()
+                    });
+                }
+                pending.clear();
+            } else if let (Some(mark), Some(ids)) = (caps.name("mark"), caps.name("mark_ids")) {
+                if !is_boundary(line, m.start(), ids.end()) {
+                    continue;
+                }
+                pending = expand_ids(ids.as_str());
+                for id in &pending {
+                    commands.push(if mark.as_str() == "#" {
+                        Command::Transition {
+                            id: id.to_owned(),
+                            keyword: String::new()
+                        }
+                    } else {
+                        Command::Relate {
+                            id: id.to_owned()
+                        }
+                    });
+                }
+            } else if let Some(cmd) = caps.name("cmd") {
+                let keyword = cmd.as_str().to_lowercase();
+                let rest = &line[m.end()..];
+                if pending.is_empty() {
+                    if keyword == "time" {
+                        if let Some((minutes, comment)) = parse_duration(rest) {
+                            worklogs.push((minutes, comment.to_owned()));
+                        }
+                        break;
+                    }
+                    pending_keyword = Some(keyword);
+                    continue;
+                }
+                match keyword.as_str() {
+                    //评论和工时使用到行尾的内容
+                    "comment" => {
+                        let text = rest.trim();
+                        if !text.is_empty() {
+                            for id in &pending {
+                                commands.push(Command::Comment {
+                                    id: id.to_owned(),
+                                    text: text.to_owned()
+                                });
+                            }
+                        }
+                        break;
+                    },
+                    "time" => {
+                        if let Some((minutes, comment)) = parse_duration(rest) {
+                            for id in &pending {
+                                commands.push(Command::Time {
+                                    id: id.to_owned(),
+                                    minutes,
+                                    comment: comment.to_owned()
+                                });
+                            }
+                        }
+                        break;
+                    },
+                    _ => {
+                        for id in &pending {
+                            commands.push(Command::Transition {
+                                id: id.to_owned(),
+                                keyword: keyword.to_owned()
+                            });
+                        }
+                    },
+                }
+            } else if caps.name("worklog").is_some() {
+                if let Some((minutes, comment)) = parse_duration(&line[m.end()..]) {
+                    if pending.is_empty() {
+                        worklogs.push((minutes, comment.to_owned()));
+                    }
+                    for id in &pending {
+                        commands.push(Command::Time {
+                            id: id.to_owned(),
+                            minutes,
+                            comment: comment.to_owned()
+                        });
+                    }
+                }
+                break;
+            } else if let Some(ids) = caps.name("ids") {
+                if !is_boundary(line, ids.start(), ids.end()) {
+                    pending.clear();
+                    pending_keyword = None;
+                    continue;
+                }
+                let ids = expand_ids(ids.as_str());
+                match pending_keyword.take() {
+                    Some(keyword) => {
+                        for id in &ids {
+                            commands.push(Command::Transition {
+                                id: id.to_owned(),
+                                keyword: keyword.to_owned()
+                            });
+                        }
+                        pending.clear();
+                    },
+                    None => pending.extend(ids)
+                }
+            }
+        }
+    }
+    let mut commands = Commands {
+        commands
+    };
+    //未指定工作项的工时登记到没有单独登记工时的工作项
+    if !worklogs.is_empty() {
+        let logged: Vec<String> = commands.worklogs().iter().map(|(id, ..)| id.to_string()).collect();
+        for id in commands.identifiers() {
+            if logged.contains(&id) {
+                continue;
+            }
+            for (minutes, comment) in &worklogs {
+                commands.commands.push(Command::Time {
+                    id: id.to_owned(),
+                    minutes: *minutes,
+                    comment: comment.to_owned()
+                });
+            }
+        }
+    }
+    commands
+}
+
+/// 前后不能紧接字母、数字或路径分隔符(排除`#foo/CD-7`、`abc-CD-7`等)
+fn is_boundary(line: &str, start: usize, end: usize) -> bool {
+    let joined = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '/';
+    !line[..start].chars().next_back().map_or(false, joined) &&
+        !line[end..].chars().next().map_or(false, joined)
+}
+
+/// 展开工作项编号列表，如: CD-7,CD-9..11
+fn expand_ids(list: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    for item in list.split(',') {
+        let mut range = item.splitn(2, "..");
+        let first = range.next().unwrap_or_default();
+        let (prefix, start) = match first.rfind('-') {
+            Some(pos) => (&first[..pos], first[pos + 1..].parse::<u64>().unwrap_or_default()),
+            None => continue
+        };
+        let end = match range.next() {
+            Some(last) => {
+                match last.rfind('-') {
+                    //范围的前缀不同时只取起始编号
+                    Some(pos) if !last[..pos].eq_ignore_ascii_case(prefix) => start,
+                    Some(pos) => last[pos + 1..].parse().unwrap_or(start),
+                    None => last.parse().unwrap_or(start)
+                }
+            },
+            None => start
+        };
+        if end < start || end - start >= MAX_RANGE {
+            ids.push(first.to_owned());
+            continue;
+        }
+        for num in start..=end {
+            let id = format!("{}-{}", prefix, num);
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+    ids
+}
+
+/// 解析工时，返回(分钟, 其余内容)，如: 1d 2h30m 内容
+fn parse_duration(text: &str) -> Option<(u64, &str)> {
+    let re = Regex::new(r"^\s*(?P<value>[0-9]+(?:\.[0-9]+)?)\s*(?P<unit>[wdhmWDHM])").unwrap();
+    let mut minutes = 0.0;
+    let mut rest = text;
+    while let Some(caps) = re.captures(rest) {
+        let end = caps.get(0).unwrap().end();
+        //单位后不能紧接字母(如2hours)
+        if rest[end..].chars().next().map_or(false, |c| c.is_ascii_alphabetic()) {
+            break;
+        }
+        let value: f64 = caps["value"].parse().ok()?;
+        minutes += value *
+            match caps["unit"].to_ascii_lowercase().as_str() {
+                "w" => (DAYS_PER_WEEK * MINUTES_PER_DAY) as f64,
+                "d" => MINUTES_PER_DAY as f64,
+                "h" => 60.0,
+                _ => 1.0
+            };
+        rest = &rest[end..];
+    }
+    if minutes <= 0.0 {
+        return None;
+    }
+    Some((minutes.round() as u64, rest.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(id: &str, keyword: &str) -> Command {
+        Command::Transition {
+            id: id.to_owned(),
+            keyword: keyword.to_owned()
+        }
+    }
+
+    fn relate(id: &str) -> Command {
+        Command::Relate {
+            id: id.to_owned()
+        }
+    }
+
+    fn time(id: &str, minutes: u64, comment: &str) -> Command {
+        Command::Time {
+            id: id.to_owned(),
+            minutes,
+            comment: comment.to_owned()
+        }
+    }
+
+    fn commands(message: &str) -> Vec<Command> { parse(message).commands }
+
+    #[test]
+    fn finish_and_relate_marks() {
+        assert_eq!(commands("#CD-7 修复登录"), vec![transition("CD-7", "")]);
+        assert_eq!(commands("@CD-7 关联"), vec![relate("CD-7")]);
+        assert_eq!(commands("#cd_web-12"), vec![transition("cd_web-12", "")]);
+    }
+
+    #[test]
+    fn lists_and_ranges() {
+        assert_eq!(commands("#CD-7,CD-8"), vec![transition("CD-7", ""), transition("CD-8", "")]);
+        assert_eq!(commands("@CD-7,CD-8"), vec![relate("CD-7"), relate("CD-8")]);
+        let range = vec![transition("CD-7", ""), transition("CD-8", ""), transition("CD-9", "")];
+        assert_eq!(commands("#CD-7..CD-9"), range);
+        assert_eq!(commands("#CD-7..9"), range);
+        assert_eq!(commands("#CD-1,CD-3..4"), vec![
+            transition("CD-1", ""),
+            transition("CD-3", ""),
+            transition("CD-4", "")
+        ]);
+        //倒序、过大或前缀不同的范围只取起始编号
+        assert_eq!(commands("#CD-9..CD-7"), vec![transition("CD-9", "")]);
+        assert_eq!(commands("#CD-1..CD-500"), vec![transition("CD-1", "")]);
+        assert_eq!(commands("#CD-1..AB-3"), vec![transition("CD-1", "")]);
+    }
+
+    #[test]
+    fn keywords_before_and_after() {
+        assert_eq!(commands("#fix CD-7"), vec![transition("CD-7", "fix")]);
+        assert_eq!(commands("#start CD-7,CD-8"), vec![
+            transition("CD-7", "start"),
+            transition("CD-8", "start")
+        ]);
+        assert_eq!(commands("CD-7 #review"), vec![transition("CD-7", "review")]);
+        assert_eq!(commands("CD-7 #Resolve"), vec![transition("CD-7", "resolve")]);
+        assert_eq!(commands("CD-7, CD-8 #review"), vec![
+            transition("CD-7", "review"),
+            transition("CD-8", "review")
+        ]);
+    }
+
+    #[test]
+    fn closing_keywords() {
+        assert_eq!(commands("fixes CD-7"), vec![transition("CD-7", "")]);
+        assert_eq!(commands("Closes: CD-8"), vec![transition("CD-8", "")]);
+        assert_eq!(commands("resolved CD-9,CD-10"), vec![transition("CD-9", ""), transition("CD-10", "")]);
+        assert_eq!(commands("prefixfixes CD-7"), vec![]);
+    }
+
+    #[test]
+    fn comment_to_end_of_line() {
+        assert_eq!(commands("CD-7 #comment 修复了登录问题 #CD-8\n下一行"), vec![
+            Command::Comment {
+                id: "CD-7".to_owned(),
+                text: "修复了登录问题 #CD-8".to_owned()
+            }
+        ]);
+        assert_eq!(commands("CD-7 #comment   "), vec![]);
+    }
+
+    #[test]
+    fn time_durations() {
+        assert_eq!(commands("CD-7 #time 2h30m 调试"), vec![time("CD-7", 150, "调试")]);
+        assert_eq!(commands("CD-7 #time 1w 1d"), vec![time("CD-7", 2880, "")]);
+        assert_eq!(commands("CD-7 #time 1.5h"), vec![time("CD-7", 90, "")]);
+        assert_eq!(commands("CD-7 工时:2h"), vec![time("CD-7", 120, "")]);
+        assert_eq!(commands("CD-7 工时： 45m 评审"), vec![time("CD-7", 45, "评审")]);
+        //无效的工时不登记
+        assert_eq!(commands("CD-7 #time 2hours"), vec![]);
+        assert_eq!(commands("CD-7 #time 0h"), vec![]);
+        assert_eq!(commands("CD-7 #time later"), vec![]);
+    }
+
+    #[test]
+    fn time_without_identifier_applies_to_all() {
+        let parsed = parse("#CD-7 @CD-8\n#time 1h review");
+        assert_eq!(parsed.worklogs(), vec![("CD-7", 60, "review"), ("CD-8", 60, "review")]);
+        //已单独登记工时的工作项不重复登记
+        let parsed = parse("#CD-7 @CD-8\nCD-8 #time 30m\n工时:1h");
+        assert_eq!(parsed.worklogs(), vec![("CD-8", 30, ""), ("CD-7", 60, "")]);
+        assert!(parse("#time 1h").commands.is_empty());
+    }
+
+    #[test]
+    fn identifiers_are_unique() {
+        let parsed = parse("#CD-7 @CD-7\nCD-7 #comment again\n@CD-8");
+        assert_eq!(parsed.identifiers(), vec!["CD-7".to_owned(), "CD-8".to_owned()]);
+        assert_eq!(parsed.transitions(), vec![("", "CD-7")]);
+    }
+
+    #[test]
+    fn rejects_junk() {
+        for message in &[
+            "#foo/CD-7",
+            "see path/CD-7",
+            "abc#CD-7",
+            "#CD-7abc",
+            "abc-CD-7 #review",
+            "https://example.com/browse/CD-7",
+            "refs/heads/CD-7",
+            "CD-7",
+            "CD-7 some text #review",
+            "#CD-",
+            "#-7",
+            "@",
+            "#7"
+        ] {
+            assert_eq!(commands(message), vec![], "{}", message);
+        }
+    }
+}
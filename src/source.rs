@@ -0,0 +1,382 @@
+//!
+//! 代码仓库的提交记录来源
+//!
+//! SVN和Git仓库都通过`RepositorySource`接口读取提交的日志、作者、日期、分支和受影响的文件
+//!
+
+use super::*;
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::{Deserialize, Serialize};
+
+/// 版本控制系统
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Vcs {
+    Svn,
+    Git
+}
+
+impl Default for Vcs {
+    fn default() -> Self { Vcs::Svn }
+}
+
+/// 提交记录
+#[derive(Debug)]
+pub struct Commit {
+    /// 显示的版本(SVN为`r版本号`，Git为SHA值的前7位)
+    pub revision: String,
+    pub message: String,
+    pub author: String,
+    pub date: chrono::NaiveDateTime,
+    /// 提交影响的分支
+    pub branches: Vec<ChangedBranch>
+}
+
+/// 分支受影响的文件
+#[derive(Debug)]
+pub struct ChangedBranch {
+    pub branch: layout::BranchRef,
+    /// 提交在该分支上的SHA值
+    pub sha: String,
+    /// 只创建引用(提交已存在)
+    pub ref_only: bool,
+    pub files: svn::FilesChanged
+}
+
+/// 提交记录来源的接口
+pub trait RepositorySource {
+    /// 读取提交记录，`id`为SVN版本号或Git提交的SHA值
+    fn commit<'a>(&'a self, id: &'a str) -> LocalBoxFuture<'a, Result<Commit, AnyError>>;
+}
+
+/// 打开提交请求对应的仓库
+pub fn open(
+    cfg: &settings::SharedConfig,
+    params: &endpoint::CommitParams
+) -> Result<Box<dyn RepositorySource>, AnyError> {
+    Ok(match params.vcs {
+        Vcs::Svn => {
+            Box::new(SvnSource {
+                repo: svn::open(cfg, &params.repo_path)?,
+                repo_name: params.repo_name.to_owned(),
+                layout: layout::Layout::load(cfg, &params.repo_name)?
+            })
+        },
+        Vcs::Git => {
+            let name = params.git_ref.as_ref().ok_or("Git提交缺少引用名称")?;
+            let (branch, tag) = git::branch_from_ref(name).ok_or(format!("不支持的引用: {}", name))?;
+            Box::new(GitSource {
+                git: git::Git::new(&params.repo_path),
+                branch: layout::BranchRef {
+                    project: None,
+                    name: branch,
+                    tag
+                }
+            })
+        }
+    })
+}
+
+/// 转换为Worktile的提交元数据
+pub fn commit_meta(commit: Commit, repo_name: &str) -> worktile::CommitMeta {
+    worktile::CommitMeta {
+        revision: commit.revision,
+        message: commit.message,
+        committer_name: commit.author,
+        committed_at: commit.date,
+        branches: commit
+            .branches
+            .into_iter()
+            .map(|changed| {
+                worktile::CommitBranch {
+                    repo: changed.branch.repository(repo_name),
+                    name: changed.branch.name,
+                    tag: changed.branch.tag,
+                    sha: changed.sha,
+                    ref_only: changed.ref_only,
+                    files_added: changed.files.added(),
+                    files_removed: changed.files.removed(),
+                    files_modified: changed.files.modified()
+                }
+            })
+            .collect()
+    }
+}
+
+/// SVN仓库
+/// 提交影响多个分支时按目录结构规则拆分受影响的文件
+struct SvnSource {
+    repo: Box<dyn svn::RepositoryReader>,
+    repo_name: String,
+    layout: layout::Layout
+}
+
+impl RepositorySource for SvnSource {
+    fn commit<'a>(&'a self, rev: &'a str) -> LocalBoxFuture<'a, Result<Commit, AnyError>> {
+        async move {
+            let uuid = self.repo.uuid().await?;
+            let revision = self.repo.revision(rev).await?;
+            let changed = revision.changed.split(&self.layout);
+            let multi_branch = changed.len() > 1;
+            let mut branches = Vec::new();
+            for svn::BranchChanged {
+                branch,
+                files
+            } in changed
+            {
+                //影响多个分支时每个分支的SHA值不同
+                let sha = if multi_branch {
+                    let repo = branch.repository(&self.repo_name);
+                    commit_sha(&uuid, rev, Some(&format!("{}/{}", repo, branch.name)))?
+                } else {
+                    commit_sha(&uuid, rev, None)?
+                };
+                branches.push(ChangedBranch {
+                    branch,
+                    sha,
+                    ref_only: false,
+                    files
+                });
+            }
+            Ok(Commit {
+                revision: format!("r{}", rev),
+                message: revision.message,
+                author: revision.author,
+                date: revision.date,
+                branches
+            })
+        }
+        .boxed_local()
+    }
+}
+
+/// Git仓库
+/// 使用提交真实的SHA值，推送标签时只创建引用
+struct GitSource {
+    git: git::Git,
+    branch: layout::BranchRef
+}
+
+impl RepositorySource for GitSource {
+    fn commit<'a>(&'a self, sha: &'a str) -> LocalBoxFuture<'a, Result<Commit, AnyError>> {
+        async move {
+            let info = self.git.commit(sha).await?;
+            let files = if self.branch.tag {
+                Default::default()
+            } else {
+                self.git.changed(sha).await?
+            };
+            Ok(Commit {
+                revision: sha.chars().take(7).collect(),
+                message: info.message,
+                author: info.author,
+                date: info.date,
+                branches: vec![ChangedBranch {
+                    branch: self.branch.clone(),
+                    sha: sha.to_owned(),
+                    ref_only: self.branch.tag,
+                    files
+                }]
+            })
+        }
+        .boxed_local()
+    }
+}
+
+/// 计算SVN提交的SHA值
+/// SHA(仓库UUID:版本号)，同一版本重复同步时保持不变，
+/// 提交影响多个分支时为SHA(仓库UUID:版本号:代码仓库/分支)
+pub fn commit_sha(uuid: &str, rev: &str, branch: Option<&str>) -> Result<String, AnyError> {
+    use openssl::hash::{Hasher, MessageDigest};
+
+    let mut hasher = Hasher::new(MessageDigest::sha1())?;
+    hasher.update(format!("{}:{}", uuid, rev.trim()).as_bytes())?;
+    if let Some(branch) = branch {
+        hasher.update(format!(":{}", branch).as_bytes())?;
+    }
+    Ok(hex::encode(hasher.finish()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svn_fixture::TestRepo;
+
+    fn params(repo: &TestRepo, rev: u64) -> endpoint::CommitParams {
+        endpoint::CommitParams {
+            repo_path: repo.path(),
+            repo_name: "repo".to_owned(),
+            rev: rev.to_string(),
+            force: false,
+            vcs: Vcs::Svn,
+            git_ref: None
+        }
+    }
+
+    async fn meta(cfg: &settings::SharedConfig, repo: &TestRepo, rev: u64) -> worktile::CommitMeta {
+        let params = params(repo, rev);
+        let source = open(cfg, &params).unwrap();
+        commit_meta(source.commit(&params.rev).await.unwrap(), &params.repo_name)
+    }
+
+    /// 返回固定提交记录的仓库
+    struct CannedRepository {
+        info: &'static str,
+        changed: &'static str
+    }
+
+    impl svn::RepositoryReader for CannedRepository {
+        fn youngest(&self) -> LocalBoxFuture<Result<u64, AnyError>> { async { Ok(7) }.boxed_local() }
+
+        fn uuid(&self) -> LocalBoxFuture<Result<String, AnyError>> {
+            async { Ok("6a1c4e0a-2f0e-4b7e-9d2a-000000000001".to_owned()) }.boxed_local()
+        }
+
+        fn revision<'a>(&'a self, rev: &'a str) -> LocalBoxFuture<'a, Result<svn::Revision, AnyError>> {
+            async move {
+                let mut lines = self.info.splitn(4, '\n');
+                Ok(svn::Revision {
+                    rev: rev.to_owned(),
+                    author: lines.next().unwrap_or_default().to_owned(),
+                    date: chrono::NaiveDate::from_ymd(2020, 5, 17).and_hms(6, 27, 23),
+                    message: lines.nth(2).unwrap_or_default().to_owned(),
+                    changed: svn::FilesChanged::parse(self.changed)?
+                })
+            }
+            .boxed_local()
+        }
+
+        fn transaction<'a>(&'a self, txn: &'a str) -> LocalBoxFuture<'a, Result<svn::Revision, AnyError>> {
+            self.revision(txn)
+        }
+    }
+
+    async fn canned_meta(cfg: &settings::SharedConfig, changed: &'static str) -> worktile::CommitMeta {
+        let source = SvnSource {
+            repo: Box::new(CannedRepository {
+                info: "zhangsan\n2020-05-17 14:27:23 +0800\n11\n#CD-7 both",
+                changed
+            }),
+            repo_name: "repo".to_owned(),
+            layout: layout::Layout::load(cfg, "repo").unwrap()
+        };
+        commit_meta(source.commit("7").await.unwrap(), "repo")
+    }
+
+    #[actix_rt::test]
+    async fn splits_canned_commit_by_branch() {
+        let cfg = settings::SharedConfig::from_toml("").unwrap();
+        let uuid = "6a1c4e0a-2f0e-4b7e-9d2a-000000000001";
+
+        //只影响一个分支时使用版本的SHA值
+        let single = canned_meta(&cfg, "U   trunk/a.txt\n").await;
+        assert_eq!((single.revision.as_str(), single.committer_name.as_str()), ("r7", "zhangsan"));
+        assert_eq!(single.branches.len(), 1);
+        assert_eq!(single.branches[0].sha, commit_sha(uuid, "7", None).unwrap());
+
+        let both = canned_meta(
+            &cfg,
+            "U   trunk/a.txt\r\nA   branches/dev/b.txt\r\nA + tags/1.0/\r\n    (from trunk/:r5)\r\n"
+        )
+        .await;
+        assert_eq!(both.message, "#CD-7 both");
+        let branches: Vec<_> = both
+            .branches
+            .iter()
+            .map(|branch| (branch.name.as_str(), branch.tag, branch.sha.clone()))
+            .collect();
+        assert_eq!(branches, vec![
+            ("trunk", false, commit_sha(uuid, "7", Some("repo/trunk")).unwrap()),
+            ("dev", false, commit_sha(uuid, "7", Some("repo/dev")).unwrap()),
+            ("1.0", true, commit_sha(uuid, "7", Some("repo/1.0")).unwrap()),
+        ]);
+        assert_eq!(both.branches[0].files_modified, vec!["trunk/a.txt"]);
+        assert_eq!(both.branches[1].files_added, vec!["branches/dev/b.txt"]);
+        assert_eq!(both.branches[2].files_added, vec!["tags/1.0"]);
+    }
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn splits_commit_by_branch() {
+        let repo = TestRepo::create();
+        repo.mkdir("branches");
+        repo.mkdir("tags");
+        repo.write("trunk/a.txt", "a");
+        let init = repo.commit("zhangsan", "init");
+        repo.copy("trunk", "branches/dev");
+        repo.commit("zhangsan", "create dev");
+        repo.write("trunk/a.txt", "a2");
+        repo.write("branches/dev/b.txt", "b");
+        let both = repo.commit("zhangsan", "#CD-7 both");
+        repo.copy("trunk", "tags/1.0");
+        let tag = repo.commit("zhangsan", "tag 1.0");
+        repo.write("tags/1.0/c.txt", "c");
+        let tag_edit = repo.commit("zhangsan", "edit tag");
+        let cfg = settings::SharedConfig::from_toml("").unwrap();
+
+        //没有匹配规则的路径归入默认分支
+        let meta_init = meta(&cfg, &repo, init).await;
+        assert_eq!(meta_init.revision, format!("r{}", init));
+        assert_eq!(meta_init.committer_name, "zhangsan");
+        assert_eq!(meta_init.branches.len(), 1);
+        assert_eq!(meta_init.branches[0].name, "trunk");
+        assert_eq!(meta_init.branches[0].sha, commit_sha(&repo.uuid(), &init.to_string(), None).unwrap());
+
+        //影响多个分支时按分支计算SHA值
+        let meta_both = meta(&cfg, &repo, both).await;
+        assert_eq!(meta_both.message, "#CD-7 both");
+        let mut names: Vec<_> = meta_both.branches.iter().map(|branch| branch.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["dev", "trunk"]);
+        let dev = meta_both.branches.iter().find(|branch| branch.name == "dev").unwrap();
+        assert_eq!(dev.repo, "repo");
+        assert!(!dev.tag);
+        assert_eq!(dev.files_added, vec!["branches/dev/b.txt"]);
+        assert_eq!(dev.sha, commit_sha(&repo.uuid(), &both.to_string(), Some("repo/dev")).unwrap());
+        let trunk = meta_both.branches.iter().find(|branch| branch.name == "trunk").unwrap();
+        assert_eq!(trunk.files_modified, vec!["trunk/a.txt"]);
+        assert_ne!(trunk.sha, dev.sha);
+
+        //复制到标签目录时为标签，标签目录中的修改按分支处理
+        let meta_tag = meta(&cfg, &repo, tag).await;
+        assert_eq!(meta_tag.branches.len(), 1);
+        assert_eq!((meta_tag.branches[0].name.as_str(), meta_tag.branches[0].tag), ("1.0", true));
+        assert_eq!(meta_tag.branches[0].files_added, vec!["tags/1.0"]);
+        let meta_tag_edit = meta(&cfg, &repo, tag_edit).await;
+        assert_eq!((meta_tag_edit.branches[0].name.as_str(), meta_tag_edit.branches[0].tag), ("1.0", false));
+    }
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn resolves_projects_with_configured_layout() {
+        let repo = TestRepo::create();
+        repo.write("app/trunk/a.txt", "a");
+        repo.write("lib/branches/v2/b.txt", "b");
+        repo.write("README.txt", "readme");
+        let rev = repo.commit("zhangsan", "projects");
+        let cfg = settings::SharedConfig::from_toml(
+            r#"
+[repositories.repo.layout]
+default_branch = "main"
+rules = [
+    { pattern = "^(?P<project>[^/]+)/branches/(?P<branch>[^/]+)" },
+    { pattern = "^(?P<project>[^/]+)/trunk(?:/|$)", branch = "trunk" }
+]
+"#
+        )
+        .unwrap();
+
+        let meta_projects = meta(&cfg, &repo, rev).await;
+        let mut branches: Vec<_> = meta_projects
+            .branches
+            .iter()
+            .map(|branch| (branch.repo.as_str(), branch.name.as_str()))
+            .collect();
+        branches.sort();
+        assert_eq!(branches, vec![("repo", "main"), ("repo/app", "trunk"), ("repo/lib", "v2")]);
+        let lib = meta_projects.branches.iter().find(|branch| branch.repo == "repo/lib").unwrap();
+        let mut files = lib.files_added.clone();
+        files.sort();
+        assert_eq!(files, vec!["lib/branches/v2", "lib/branches/v2/b.txt"]);
+    }
+}
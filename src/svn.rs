@@ -1,21 +1,218 @@
 //!
 //! SVN提交记录提取封装
 //!
+//! 通过`RepositoryReader`接口访问仓库，一次读取版本的作者、日期、日志和受影响的路径
+//!
 
-use super::AnyError;
-use encoding::{all::GBK as CMDCS, DecoderTrap, Encoding};
+use super::{layout, settings, AnyError};
+use encoding::{all::GBK, label, DecoderTrap, Encoding, EncodingRef};
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use std::fmt;
 use tokio::process::Command;
 
-pub async fn commit_message(repo_path: &str, rev: &str) -> Result<String, AnyError> {
-    svnlook(&["log", repo_path, "-r", rev]).await
+/// svnlook输出的文本编码
+#[derive(Clone, Copy)]
+pub enum TextEncoding {
+    /// 自动检测：优先按UTF-8解码，失败时按GBK解码
+    Auto,
+    Utf8,
+    /// 其它编码
+    Other(EncodingRef)
+}
+
+impl TextEncoding {
+    /// 解析编码名称，支持`auto`、WHATWG编码标签(如`utf-8`、`gbk`、`big5`)和Windows代码页(如`936`)
+    pub fn from_label(name: &str) -> Result<TextEncoding, AnyError> {
+        let name = name.trim().to_lowercase();
+        if name == "auto" {
+            return Ok(TextEncoding::Auto);
+        }
+        let enc = match name.parse::<usize>() {
+            Ok(cp) => label::encoding_from_windows_code_page(cp),
+            Err(_) => label::encoding_from_whatwg_label(&name)
+        }
+        .ok_or(format!("不支持的编码: {}", name))?;
+        if enc.whatwg_name() == Some("utf-8") {
+            Ok(TextEncoding::Utf8)
+        } else {
+            Ok(TextEncoding::Other(enc))
+        }
+    }
+
+    /// 是否按UTF-8输出
+    fn is_utf8(&self) -> bool {
+        match self {
+            TextEncoding::Auto | TextEncoding::Utf8 => true,
+            TextEncoding::Other(_) => false
+        }
+    }
+
+    pub fn decode(&self, buf: &[u8]) -> Result<String, AnyError> {
+        match self {
+            TextEncoding::Auto => {
+                match std::str::from_utf8(buf) {
+                    Ok(text) => Ok(text.to_owned()),
+                    Err(_) => Ok(GBK.decode(buf, DecoderTrap::Replace)?)
+                }
+            },
+            TextEncoding::Utf8 => Ok(String::from_utf8_lossy(buf).into_owned()),
+            TextEncoding::Other(enc) => Ok(enc.decode(buf, DecoderTrap::Replace)?)
+        }
+    }
+}
+
+impl fmt::Debug for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextEncoding::Auto => write!(f, "auto"),
+            TextEncoding::Utf8 => write!(f, "utf-8"),
+            TextEncoding::Other(enc) => write!(f, "{}", enc.name())
+        }
+    }
+}
+
+/// 版本的提交记录
+#[derive(Debug)]
+pub struct Revision {
+    pub rev: String,
+    pub author: String,
+    pub date: chrono::NaiveDateTime,
+    pub message: String,
+    pub changed: FilesChanged
+}
+
+/// SVN仓库的访问接口
+pub trait RepositoryReader {
+    /// 最新的版本号
+    fn youngest(&self) -> LocalBoxFuture<Result<u64, AnyError>>;
+
+    /// 仓库的UUID
+    fn uuid(&self) -> LocalBoxFuture<Result<String, AnyError>>;
+
+    /// 一次读取版本的全部提交记录
+    fn revision<'a>(&'a self, rev: &'a str) -> LocalBoxFuture<'a, Result<Revision, AnyError>>;
+
+    /// 读取未提交事务的提交记录(pre-commit钩子)
+    fn transaction<'a>(&'a self, txn: &'a str) -> LocalBoxFuture<'a, Result<Revision, AnyError>>;
+}
+
+/// 打开SVN仓库
+pub fn open(cfg: &settings::SharedConfig, repo_path: &str) -> Result<Box<dyn RepositoryReader>, AnyError> {
+    Ok(Box::new(Svnlook::new(cfg, repo_path)?))
+}
+
+/// 通过svnlook命令访问仓库
+/// 每个版本只运行`svnlook info`和`svnlook changed`两次命令(并行)
+#[derive(Debug, Clone)]
+pub struct Svnlook {
+    repo_path: String,
+    encoding: TextEncoding,
+    /// 运行svnlook使用的区域设置(非Windows系统)
+    locale: String
+}
+
+impl Svnlook {
+    /// 使用`[svn]`配置的编码访问仓库
+    pub fn new(cfg: &settings::SharedConfig, repo_path: &str) -> Result<Svnlook, AnyError> {
+        Ok(Svnlook {
+            repo_path: repo_path.to_owned(),
+            encoding: TextEncoding::from_label(&cfg.config_string("svn.encoding"))?,
+            locale: cfg.config_string("svn.locale")
+        })
+    }
+
+    /// 读取版本(`-r`)或事务(`-t`)的提交记录
+    async fn read(&self, flag: &str, id: &str) -> Result<Revision, AnyError> {
+        let (info, changed) =
+            future::try_join(self.run(&["info", flag, id]), self.run(&["changed", "--copy-info", flag, id]))
+                .await?;
+        let (author, date, message) = Svnlook::parse_info(id, &info)?;
+        Ok(Revision {
+            rev: id.to_owned(),
+            author,
+            date,
+            message,
+            changed: FilesChanged::parse(&changed)?
+        })
+    }
+
+    /// 解析`svnlook info`的输出
+    ///
+    /// 依次为: 作者、日期、日志的长度、日志(可能有多行)
+    fn parse_info(rev: &str, info: &str) -> Result<(String, chrono::NaiveDateTime, String), AnyError> {
+        let mut lines = info.splitn(4, '\n');
+        let mut next_line = || lines.next().map(|line| line.trim_end_matches('\r').to_owned());
+        let (author, date, _log_size) = match (next_line(), next_line(), next_line()) {
+            (Some(author), Some(date), Some(log_size)) => (author, date, log_size),
+            _ => return Err(format!("无法解析[{}]的提交信息: {}", rev, info).into())
+        };
+        //Windows下svnlook输出的换行为\r\n
+        let message = next_line().unwrap_or_default().replace("\r\n", "\n");
+        Ok((author, parse_date(date)?, message))
+    }
+
+    /// 运行`svnlook 子命令 仓库路径 参数`
+    async fn run(&self, args: &[&str]) -> Result<String, AnyError> {
+        let (subcommand, args) = args.split_first().ok_or("缺少svnlook子命令")?;
+        let mut cmd = Command::new("svnlook");
+        cmd.arg(subcommand).arg(&self.repo_path).args(args);
+        //svnlook按区域设置转换输出的编码，使用UTF-8时指定UTF-8的区域设置
+        if cfg!(not(windows)) && self.encoding.is_utf8() && !self.locale.is_empty() {
+            cmd.env("LC_ALL", &self.locale);
+        }
+        let output = cmd.output().await?;
+        if output.status.success() {
+            let mut rv = self.encoding.decode(&output.stdout)?;
+            //去除结尾的\r\n
+            if rv.ends_with('\n') {
+                rv.pop();
+                if rv.ends_with('\r') {
+                    rv.pop();
+                }
+            }
+            Ok(rv)
+        } else {
+            let buf = if output.stderr.len() > 0 {
+                &output.stderr
+            } else {
+                &output.stdout
+            };
+            let err = self.encoding.decode(buf)?;
+            let mut err = err.trim();
+            if err.is_empty() {
+                err = "(EMPTY)";
+            }
+            let cmdline = format!("svnlook {} {} {}", subcommand, self.repo_path, args.join(" "));
+            warn!("{}, stderr: {}", cmdline.trim_end(), err);
+            Err(format!("{}, {}", cmdline.trim_end(), err).into())
+        }
+    }
 }
 
-pub async fn commit_author(repo_path: &str, rev: &str) -> Result<String, AnyError> {
-    svnlook(&["author", repo_path, "-r", rev]).await
+impl RepositoryReader for Svnlook {
+    fn youngest(&self) -> LocalBoxFuture<Result<u64, AnyError>> {
+        async move {
+            let rev = self.run(&["youngest"]).await?;
+            rev.trim().parse().map_err(|e| format!("解析版本号: {}, 失败: {}", rev, e).into())
+        }
+        .boxed_local()
+    }
+
+    fn uuid(&self) -> LocalBoxFuture<Result<String, AnyError>> {
+        async move { self.run(&["uuid"]).await.map(|uuid| uuid.trim().to_owned()) }.boxed_local()
+    }
+
+    fn revision<'a>(&'a self, rev: &'a str) -> LocalBoxFuture<'a, Result<Revision, AnyError>> {
+        self.read("-r", rev).boxed_local()
+    }
+
+    fn transaction<'a>(&'a self, txn: &'a str) -> LocalBoxFuture<'a, Result<Revision, AnyError>> {
+        self.read("-t", txn).boxed_local()
+    }
 }
 
-pub async fn commit_date(repo_path: &str, rev: &str) -> Result<chrono::NaiveDateTime, AnyError> {
-    let mut date = svnlook(&["date", repo_path, "-r", rev]).await?;
+/// 解析svnlook date的输出
+fn parse_date(mut date: String) -> Result<chrono::NaiveDateTime, AnyError> {
     //svnlook date返回的日期格式为： 2020-05-17 14:27:23 +0800 (周日, 17 5月 2020)
     date.truncate(date.find(" (").unwrap_or(date.len()));
     chrono::DateTime::parse_from_str(&date, "%Y-%m-%d %H:%M:%S %z")
@@ -23,82 +220,382 @@ pub async fn commit_date(repo_path: &str, rev: &str) -> Result<chrono::NaiveDate
         .map_err(|e| format!("解析日期: {}, 失败: {}", date, e).into())
 }
 
-pub async fn commit_branch(repo_path: &str, rev: &str) -> Result<Option<String>, AnyError> {
-    use regex::Regex;
-
-    let changed = svnlook(&["dirs-changed", repo_path, "-r", rev]).await?;
+/// 变更类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeStatus {
+    /// 新增(A)
+    Added,
+    /// 删除(D)
+    Deleted,
+    /// 内容修改(U)
+    Updated,
+    /// 替换(R)
+    Replaced,
+    /// 内容未修改，只修改了属性(_)
+    Unchanged
+}
 
-    //提取branches、branch和tags路径的分支名称
-    let re = Regex::new(r"(?m).*/(?:branches|branch|tags)/(\w+)/.*")?;
-    if let Some(branches) = re.captures(&changed) {
-        if let Some(branch) = branches.get(1) {
-            return Ok(Some(branch.as_str().to_owned()));
-        }
-    }
+/// 节点类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Dir
+}
 
-    return Ok(None);
+/// 受影响的路径
+#[derive(Debug, Clone)]
+pub struct ChangedPath {
+    /// 路径(不包括结尾的/)
+    pub path: String,
+    pub status: ChangeStatus,
+    /// 是否修改了属性
+    pub props_changed: bool,
+    /// 复制来源的路径和版本号
+    pub copied_from: Option<(String, u64)>,
+    pub kind: NodeKind
 }
 
+/// 提交受影响的路径列表
+#[derive(Debug, Default)]
 pub struct FilesChanged {
-    pub added: Vec<String>,
-    pub removed: Vec<String>,
-    pub modified: Vec<String>
-}
-
-pub async fn commit_changed(repo_path: &str, rev: &str) -> Result<FilesChanged, AnyError> {
-    let changed = svnlook(&["changed", repo_path, "-r", rev]).await?;
-    let mut added = Vec::new();
-    let mut removed = Vec::new();
-    let mut modified = Vec::new();
-    for line in changed.split("\r\n") {
-        let mut target = None;
-        if line.starts_with("A") {
-            target = Some(&mut added);
-        } else if line.starts_with("D") {
-            target = Some(&mut removed);
-        } else if line.starts_with("U") {
-            target = Some(&mut modified);
-        }
-        if let Some(target) = target {
-            let mut line = line[1..].trim_start().to_owned();
-            //去除结尾的/
-            if line.ends_with('/') {
-                line.pop();
+    pub paths: Vec<ChangedPath>
+}
+
+/// 分支受影响的文件
+#[derive(Debug)]
+pub struct BranchChanged {
+    pub branch: layout::BranchRef,
+    pub files: FilesChanged
+}
+
+impl FilesChanged {
+    /// 解析`svnlook changed --copy-info`的输出
+    ///
+    /// 每行前4列依次为: 变更类型(A/D/U/R/_)、属性修改(U)、复制历史(+)、空格，之后为路径，
+    /// 目录以/结尾，带有复制历史的路径下一行为复制来源，如:
+    /// ```text
+    /// A + tags/1.0/
+    ///     (from trunk/:r5)
+    /// _U  trunk/
+    /// ```
+    pub fn parse(output: &str) -> Result<FilesChanged, AnyError> {
+        let mut paths: Vec<ChangedPath> = Vec::new();
+        for line in output.lines() {
+            if line.trim().is_empty() {
+                continue;
             }
-            target.push(line);
+            //复制来源
+            if line.starts_with(' ') {
+                let from = line.trim();
+                if from.starts_with("(from ") && from.ends_with(')') {
+                    let from = &from[6..from.len() - 1];
+                    let pos = from.rfind(":r").ok_or(format!("无法解析复制来源: {}", line))?;
+                    let rev = from[pos + 2..].parse().map_err(|_| format!("无法解析复制来源: {}", line))?;
+                    let mut path = from[..pos].to_owned();
+                    if path.ends_with('/') {
+                        path.pop();
+                    }
+                    if let Some(last) = paths.last_mut() {
+                        last.copied_from = Some((path, rev));
+                    }
+                    continue;
+                }
+                return Err(format!("无法解析受影响的路径: {}", line).into());
+            }
+            if line.len() < 5 || !line.is_char_boundary(4) {
+                return Err(format!("无法解析受影响的路径: {}", line).into());
+            }
+            let flags = line.as_bytes();
+            let status = match flags[0] {
+                b'A' => ChangeStatus::Added,
+                b'D' => ChangeStatus::Deleted,
+                b'U' => ChangeStatus::Updated,
+                b'R' => ChangeStatus::Replaced,
+                b'_' => ChangeStatus::Unchanged,
+                _ => return Err(format!("未知的变更类型: {}", line).into())
+            };
+            let mut path = line[4..].to_owned();
+            let kind = if path.ends_with('/') {
+                path.pop();
+                NodeKind::Dir
+            } else {
+                NodeKind::File
+            };
+            paths.push(ChangedPath {
+                path,
+                status,
+                props_changed: flags[1] == b'U',
+                copied_from: None,
+                kind
+            });
         }
+        Ok(FilesChanged {
+            paths
+        })
     }
-    Ok(FilesChanged {
-        added,
-        removed,
-        modified
-    })
-}
-
-async fn svnlook(args: &[&str]) -> Result<String, AnyError> {
-    let output = Command::new("svnlook").args(args).output().await?;
-    if output.status.success() {
-        let mut rv = CMDCS.decode(&output.stdout, DecoderTrap::Replace)?;
-        //去除结尾的\r\n
-        if rv.ends_with('\n') {
-            rv.pop();
-            if rv.ends_with('\r') {
-                rv.pop();
+
+    /// 新增的路径
+    pub fn added(&self) -> Vec<String> { self.filter(|changed| changed.status == ChangeStatus::Added) }
+
+    /// 删除的路径
+    pub fn removed(&self) -> Vec<String> { self.filter(|changed| changed.status == ChangeStatus::Deleted) }
+
+    /// 修改的路径(包括替换和只修改了属性的路径)
+    pub fn modified(&self) -> Vec<String> {
+        self.filter(|changed| {
+            match changed.status {
+                ChangeStatus::Updated | ChangeStatus::Replaced => true,
+                ChangeStatus::Unchanged => changed.props_changed,
+                _ => false
+            }
+        })
+    }
+
+    fn filter(&self, f: impl Fn(&ChangedPath) -> bool) -> Vec<String> {
+        self.paths.iter().filter(|changed| f(changed)).map(|changed| changed.path.to_owned()).collect()
+    }
+
+    /// 按分支拆分受影响的文件，没有匹配规则的文件归入默认分支
+    /// 标签目录中没有复制操作时按分支处理
+    pub fn split(self, layout: &layout::Layout) -> Vec<BranchChanged> {
+        let mut branches: Vec<BranchChanged> = Vec::new();
+        for changed in self.paths {
+            let branch = layout.resolve(&changed.path).unwrap_or_else(|| layout.default_branch());
+            match branches.iter_mut().find(|branch_changed| branch_changed.branch == branch) {
+                Some(branch_changed) => branch_changed.files.paths.push(changed),
+                None => {
+                    branches.push(BranchChanged {
+                        branch,
+                        files: FilesChanged {
+                            paths: vec![changed]
+                        }
+                    })
+                },
             }
         }
-        Ok(rv)
-    } else {
-        let buf = if output.stderr.len() > 0 {
-            &output.stderr
-        } else {
-            &output.stdout
-        };
-        let err = CMDCS.decode(buf, DecoderTrap::Replace)?;
-        let mut err = err.trim();
-        if err.is_empty() {
-            err = "(EMPTY)";
+        for changed in &mut branches {
+            if changed.branch.tag && !changed.files.paths.iter().any(|path| path.copied_from.is_some()) {
+                changed.branch.tag = false;
+            }
         }
-        warn!("svnlook {}, stderr: {}", args.join(" "), err);
-        Err(format!("svnlook {}, {}", args.join(" "), err).into())
+        branches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svn_fixture::TestRepo;
+    use encoding::EncoderTrap;
+
+    fn svnlook(repo: &TestRepo) -> Svnlook {
+        Svnlook::new(&settings::SharedConfig::from_toml("").unwrap(), &repo.path()).unwrap()
+    }
+
+    fn find<'a>(changed: &'a FilesChanged, path: &str) -> &'a ChangedPath {
+        changed.paths.iter().find(|changed| changed.path == path).expect(path)
+    }
+
+    fn sorted(mut paths: Vec<String>) -> Vec<String> {
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn parses_changed_status_props_and_kind() {
+        let changed = FilesChanged::parse(
+            "A   trunk/\nA   trunk/a.txt\nD   trunk/old.txt\nU   trunk/b.txt\nUU  trunk/c.txt\n_U  trunk/\nR   trunk/d.txt"
+        )
+        .unwrap();
+        assert_eq!(changed.paths.len(), 7);
+        let dir = &changed.paths[0];
+        assert_eq!((dir.path.as_str(), dir.status, dir.kind), ("trunk", ChangeStatus::Added, NodeKind::Dir));
+        let file = find(&changed, "trunk/a.txt");
+        assert_eq!(
+            (file.status, file.kind, file.props_changed),
+            (ChangeStatus::Added, NodeKind::File, false)
+        );
+        assert_eq!(find(&changed, "trunk/old.txt").status, ChangeStatus::Deleted);
+        assert!(!find(&changed, "trunk/b.txt").props_changed);
+        let both = find(&changed, "trunk/c.txt");
+        assert_eq!((both.status, both.props_changed), (ChangeStatus::Updated, true));
+        let props = &changed.paths[5];
+        assert_eq!(
+            (props.status, props.props_changed, props.kind),
+            (ChangeStatus::Unchanged, true, NodeKind::Dir)
+        );
+        assert_eq!(find(&changed, "trunk/d.txt").status, ChangeStatus::Replaced);
+
+        assert_eq!(changed.added(), vec!["trunk", "trunk/a.txt"]);
+        assert_eq!(changed.removed(), vec!["trunk/old.txt"]);
+        assert_eq!(changed.modified(), vec!["trunk/b.txt", "trunk/c.txt", "trunk", "trunk/d.txt"]);
+    }
+
+    #[test]
+    fn parses_copy_history() {
+        let changed = FilesChanged::parse(
+            "A + tags/1.0/\n    (from trunk/:r5)\nR + trunk/a.txt\n    (from branches/dev/a.txt:r12)\nA   tags/1.0/b.txt"
+        )
+        .unwrap();
+        assert_eq!(changed.paths.len(), 3);
+        let tag = find(&changed, "tags/1.0");
+        assert_eq!((tag.status, tag.kind), (ChangeStatus::Added, NodeKind::Dir));
+        assert_eq!(tag.copied_from, Some(("trunk".to_owned(), 5)));
+        let replaced = find(&changed, "trunk/a.txt");
+        assert_eq!(replaced.status, ChangeStatus::Replaced);
+        assert_eq!(replaced.copied_from, Some(("branches/dev/a.txt".to_owned(), 12)));
+        assert_eq!(find(&changed, "tags/1.0/b.txt").copied_from, None);
+    }
+
+    #[test]
+    fn parses_info_with_unix_and_windows_line_endings() {
+        let date = chrono::NaiveDate::from_ymd(2020, 5, 17).and_hms(6, 27, 23);
+        for newline in &["\n", "\r\n"] {
+            let info = [
+                "zhangsan",
+                "2020-05-17 14:27:23 +0800 (周日, 17 5月 2020)",
+                "29",
+                "#CD-7 first line",
+                "",
+                "second line"
+            ]
+            .join(newline);
+            let (author, parsed_date, message) = Svnlook::parse_info("5", &info).unwrap();
+            assert_eq!((author.as_str(), parsed_date), ("zhangsan", date));
+            assert_eq!(message, "#CD-7 first line\n\nsecond line");
+        }
+        //没有日志
+        let (_, _, message) = Svnlook::parse_info("5", "zhangsan\r\n2020-05-17 14:27:23 +0800\r\n0").unwrap();
+        assert_eq!(message, "");
+        assert!(Svnlook::parse_info("5", "zhangsan\n2020-05-17 14:27:23 +0800").is_err());
+        assert!(Svnlook::parse_info("5", "zhangsan\nyesterday\n0").is_err());
+    }
+
+    #[test]
+    fn parses_changed_with_windows_line_endings() {
+        let changed =
+            FilesChanged::parse("A + tags/1.0/\r\n    (from trunk/:r5)\r\n_U  trunk/\r\nU   trunk/a.txt\r\n")
+                .unwrap();
+        assert_eq!(changed.paths.len(), 3);
+        let tag = find(&changed, "tags/1.0");
+        assert_eq!((tag.kind, tag.copied_from.clone()), (NodeKind::Dir, Some(("trunk".to_owned(), 5))));
+        let trunk = find(&changed, "trunk");
+        assert_eq!(
+            (trunk.status, trunk.props_changed, trunk.kind),
+            (ChangeStatus::Unchanged, true, NodeKind::Dir)
+        );
+        assert_eq!(find(&changed, "trunk/a.txt").kind, NodeKind::File);
+    }
+
+    #[test]
+    fn splits_changed_by_branch() {
+        let layout = layout::Layout::load(&settings::SharedConfig::from_toml("").unwrap(), "repo").unwrap();
+        let changed = FilesChanged::parse(
+            "U   trunk/a.txt\nA   branches/dev-1.2/b.txt\nA + tags/1.0/\n    (from trunk/:r5)\nU   tags/0.9/c.txt\n_U  README.txt"
+        )
+        .unwrap();
+        let branches = changed.split(&layout);
+        let names: Vec<(&str, bool, Vec<String>)> = branches
+            .iter()
+            .map(|changed| (changed.branch.name.as_str(), changed.branch.tag, changed.files.filter(|_| true)))
+            .collect();
+        assert_eq!(names, vec![
+            ("trunk", false, vec!["trunk/a.txt".to_owned(), "README.txt".to_owned()]),
+            ("dev-1.2", false, vec!["branches/dev-1.2/b.txt".to_owned()]),
+            ("1.0", true, vec!["tags/1.0".to_owned()]),
+            //标签目录中没有复制操作时按分支处理
+            ("0.9", false, vec!["tags/0.9/c.txt".to_owned()]),
+        ]);
+    }
+
+    #[test]
+    fn rejects_malformed_changed_lines() {
+        assert!(FilesChanged::parse("X   trunk/a.txt").is_err());
+        assert!(FilesChanged::parse("A").is_err());
+        assert!(FilesChanged::parse("A + tags/1.0/\n    (from trunk/:rX)").is_err());
+        assert!(FilesChanged::parse("    unexpected").is_err());
+        assert!(FilesChanged::parse("").unwrap().paths.is_empty());
+    }
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn reads_revision_info() {
+        let repo = TestRepo::create();
+        repo.mkdir("trunk");
+        repo.write("trunk/a.txt", "a");
+        let rev = repo.commit("zhangsan", "#CD-7 first line\n\nsecond line");
+        repo.set_date(rev, "2020-05-17T06:27:23.000000Z");
+
+        let svnlook = svnlook(&repo);
+        assert_eq!(svnlook.youngest().await.unwrap(), rev);
+        assert_eq!(svnlook.uuid().await.unwrap(), repo.uuid());
+        let revision = svnlook.revision(&rev.to_string()).await.unwrap();
+        assert_eq!(revision.author, "zhangsan");
+        assert_eq!(revision.message, "#CD-7 first line\n\nsecond line");
+        assert_eq!(revision.date, chrono::NaiveDate::from_ymd(2020, 5, 17).and_hms(6, 27, 23));
+        let trunk = find(&revision.changed, "trunk");
+        assert_eq!((trunk.status, trunk.kind), (ChangeStatus::Added, NodeKind::Dir));
+        let file = find(&revision.changed, "trunk/a.txt");
+        assert_eq!((file.status, file.kind), (ChangeStatus::Added, NodeKind::File));
+        assert_eq!(sorted(revision.changed.added()), vec!["trunk", "trunk/a.txt"]);
+    }
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn reads_copies_and_property_changes() {
+        let repo = TestRepo::create();
+        repo.mkdir("tags");
+        repo.write("trunk/a.txt", "a");
+        repo.write("trunk/b.txt", "b");
+        let base = repo.commit("zhangsan", "init");
+        repo.copy("trunk", "tags/1.0");
+        let tag_rev = repo.commit("lisi", "tag 1.0");
+        repo.propset("svn:ignore", "*.log", "trunk");
+        repo.propset("svn:eol-style", "native", "trunk/a.txt");
+        repo.write("trunk/a.txt", "a2");
+        repo.remove("trunk/b.txt");
+        let props_rev = repo.commit("lisi", "props");
+
+        let svnlook = svnlook(&repo);
+        //复制目录时只列出复制的根路径
+        let tag = svnlook.revision(&tag_rev.to_string()).await.unwrap();
+        assert_eq!(tag.author, "lisi");
+        assert_eq!(tag.changed.paths.len(), 1);
+        let copied = &tag.changed.paths[0];
+        assert_eq!(copied.path, "tags/1.0");
+        assert_eq!((copied.status, copied.kind), (ChangeStatus::Added, NodeKind::Dir));
+        assert_eq!(copied.copied_from, Some(("trunk".to_owned(), base)));
+
+        let props = svnlook.revision(&props_rev.to_string()).await.unwrap();
+        let trunk = find(&props.changed, "trunk");
+        assert_eq!((trunk.status, trunk.props_changed), (ChangeStatus::Unchanged, true));
+        let file = find(&props.changed, "trunk/a.txt");
+        assert_eq!((file.status, file.props_changed), (ChangeStatus::Updated, true));
+        assert_eq!(sorted(props.changed.modified()), vec!["trunk", "trunk/a.txt"]);
+        assert_eq!(props.changed.removed(), vec!["trunk/b.txt"]);
+        assert!(props.changed.added().is_empty());
+    }
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn decodes_non_ascii_log_and_paths() {
+        let repo = TestRepo::create();
+        repo.write("trunk/文档/说明.txt", "内容");
+        //日志以GBK编码提交，仓库中保存为UTF-8
+        let message = GBK.encode("#CD-7 修复中文日志", EncoderTrap::Strict).unwrap();
+        let rev = repo.commit_encoded("王五", &message, "GBK");
+
+        let revision = svnlook(&repo).revision(&rev.to_string()).await.unwrap();
+        assert_eq!(revision.author, "王五");
+        assert_eq!(revision.message, "#CD-7 修复中文日志");
+        assert_eq!(sorted(revision.changed.added()), vec!["trunk", "trunk/文档", "trunk/文档/说明.txt"]);
+    }
+
+    #[actix_rt::test]
+    #[ignore]
+    async fn missing_revision_is_error() {
+        let repo = TestRepo::create();
+        let e = svnlook(&repo).revision("5").await.unwrap_err();
+        assert!(e.to_string().starts_with("svnlook "));
     }
 }
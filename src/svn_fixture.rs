@@ -0,0 +1,168 @@
+//!
+//! 测试用的SVN仓库
+//!
+//! 在临时目录中使用`svnadmin create`创建仓库，通过`file://`地址检出工作副本后修改并提交，
+//! Drop时删除临时目录。使用仓库的测试需要安装svn、svnadmin和svnlook，标记为`#[ignore]`，
+//! 通过`cargo test -- --ignored`运行，未安装时测试失败
+//!
+
+use std::{
+    ffi::OsStr, fs, path::{Path, PathBuf}, process::Command
+};
+
+/// 运行svn命令使用的区域设置，与`svn.locale`的默认值一致
+const LOCALE: &'static str = "C.UTF-8";
+
+/// 临时的SVN仓库和工作副本
+pub struct TestRepo {
+    /// 临时目录
+    dir: PathBuf,
+    /// 仓库路径
+    repo: PathBuf,
+    /// 工作副本路径
+    wc: PathBuf
+}
+
+impl TestRepo {
+    /// 创建空仓库并检出工作副本，未安装svn时panic
+    pub fn create() -> TestRepo {
+        if let Some(program) = ["svn", "svnadmin", "svnlook"].iter().find(|program| !installed(program)) {
+            panic!("{} not found, install subversion to run the svn tests", program);
+        }
+        let dir = std::env::temp_dir().join(format!(
+            "svn_commit_wt-{}-{}",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let repo = TestRepo {
+            repo: dir.join("repo"),
+            wc: dir.join("wc"),
+            dir
+        };
+        run(&repo.dir, "svnadmin", &[OsStr::new("create"), repo.repo.as_os_str()]);
+        let url = repo.url();
+        repo.svn(&["checkout", "--quiet", &url, &repo.wc.to_string_lossy()]);
+        repo
+    }
+
+    /// 仓库路径(svnlook使用)
+    pub fn path(&self) -> String { self.repo.to_string_lossy().into_owned() }
+
+    /// 临时目录中的文件路径(台账等使用)
+    pub fn temp(&self, name: &str) -> String { self.dir.join(name).to_string_lossy().into_owned() }
+
+    /// 仓库的UUID
+    pub fn uuid(&self) -> String {
+        let uuid = fs::read_to_string(self.repo.join("db").join("uuid")).unwrap();
+        uuid.lines().next().unwrap_or_default().trim().to_owned()
+    }
+
+    /// 创建目录
+    pub fn mkdir(&self, path: &str) { self.svn(&["mkdir", "--quiet", "--parents", path]); }
+
+    /// 写入文件，新文件同时添加到版本控制
+    pub fn write(&self, path: &str, content: impl AsRef<[u8]>) {
+        let file = self.wc.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, content).unwrap();
+        self.svn(&["add", "--quiet", "--force", "--parents", path]);
+    }
+
+    /// 删除文件或目录
+    pub fn remove(&self, path: &str) { self.svn(&["delete", "--quiet", path]); }
+
+    /// 复制文件或目录(保留复制历史)
+    pub fn copy(&self, from: &str, to: &str) { self.svn(&["copy", "--quiet", "--parents", from, to]); }
+
+    /// 设置属性
+    pub fn propset(&self, name: &str, value: &str, path: &str) {
+        self.svn(&["propset", "--quiet", name, value, path]);
+    }
+
+    /// 提交全部修改，返回新的版本号
+    pub fn commit(&self, author: &str, message: &str) -> u64 {
+        self.commit_encoded(author, message.as_bytes(), "UTF-8")
+    }
+
+    /// 使用指定编码的日志提交全部修改，返回新的版本号
+    pub fn commit_encoded(&self, author: &str, message: &[u8], encoding: &str) -> u64 {
+        let file = self.dir.join("message.txt");
+        fs::write(&file, message).unwrap();
+        self.svn(&[
+            "commit",
+            "--quiet",
+            "--username",
+            author,
+            "--encoding",
+            encoding,
+            "--file",
+            &file.to_string_lossy()
+        ]);
+        //更新工作副本，避免之后的修改基于旧版本
+        self.svn(&["update", "--quiet"]);
+        let youngest = run(&self.dir, "svnlook", &[OsStr::new("youngest"), self.repo.as_os_str()]);
+        youngest.trim().parse().unwrap()
+    }
+
+    /// 修改版本的提交日期，如: 2020-05-17T06:27:23.000000Z
+    pub fn set_date(&self, rev: u64, date: &str) {
+        let file = self.dir.join("date.txt");
+        fs::write(&file, date).unwrap();
+        run(&self.dir, "svnadmin", &[
+            OsStr::new("setrevprop"),
+            self.repo.as_os_str(),
+            OsStr::new("-r"),
+            OsStr::new(&rev.to_string()),
+            OsStr::new("svn:date"),
+            file.as_os_str()
+        ]);
+    }
+
+    /// 仓库的file://地址
+    fn url(&self) -> String {
+        let path = self.repo.to_string_lossy().replace('\\', "/");
+        if path.starts_with('/') {
+            format!("file://{}", path)
+        } else {
+            format!("file:///{}", path)
+        }
+    }
+
+    /// 在工作副本中运行svn命令，使用独立的配置目录，不受本机配置影响
+    fn svn(&self, args: &[&str]) {
+        let config_dir = self.dir.join("config");
+        let mut all: Vec<&OsStr> =
+            vec![OsStr::new("--non-interactive"), OsStr::new("--config-dir"), config_dir.as_os_str()];
+        all.extend(args.iter().map(OsStr::new));
+        let cwd = if self.wc.exists() {
+            &self.wc
+        } else {
+            &self.dir
+        };
+        run(cwd, "svn", &all);
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) { let _ = fs::remove_dir_all(&self.dir); }
+}
+
+/// 命令是否可以运行
+fn installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .arg("--quiet")
+        .output()
+        .map_or(false, |output| output.status.success())
+}
+
+/// 运行命令，失败时panic，返回标准输出
+fn run(cwd: &Path, program: &str, args: &[&OsStr]) -> String {
+    let output =
+        Command::new(program).args(args).current_dir(cwd).env("LC_ALL", LOCALE).output().expect(program);
+    if !output.status.success() {
+        panic!("{} {:?} failed: {}", program, args, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
@@ -0,0 +1,116 @@
+//!
+//! Worktile访问令牌的管理
+//!
+//! 令牌过期前由后台任务提前刷新，并发的刷新请求合并为一次，
+//! 令牌无效或已失效时由调用方作废令牌后重新获取
+//!
+
+use super::AnyError;
+use futures::lock::Mutex;
+use std::{future::Future, sync::RwLock};
+
+/// 令牌提前刷新的时间(小时)，有效期较短时为有效期的一半
+const REFRESH_AHEAD_HOURS: i64 = 12;
+
+/// 令牌过期前停止使用的时间(秒)，避免请求途中过期
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// 访问令牌
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub token: String,
+    pub expires_in: chrono::NaiveDateTime,
+    /// 获取令牌的时间，缓存中没有记录时为`None`(按剩余的有效期计算)
+    pub issued_at: Option<chrono::NaiveDateTime>
+}
+
+impl Token {
+    /// 是否可以使用(未过期)
+    fn is_usable(&self) -> bool {
+        self.expires_in.signed_duration_since(chrono::Utc::now().naive_utc()).num_seconds() >
+            EXPIRY_MARGIN_SECS
+    }
+
+    /// 需要提前刷新的时间，过期前12小时与有效期的一半中较短的一个
+    pub fn refresh_at(&self) -> chrono::NaiveDateTime {
+        let issued_at = self.issued_at.unwrap_or_else(|| chrono::Utc::now().naive_utc());
+        let half_lifetime = (self.expires_in - issued_at) / 2;
+        let ahead = std::cmp::min(chrono::Duration::hours(REFRESH_AHEAD_HOURS), half_lifetime);
+        self.expires_in - std::cmp::max(ahead, chrono::Duration::zero())
+    }
+}
+
+/// 访问令牌的管理
+pub struct TokenManager {
+    /// 当前的令牌
+    token: RwLock<Option<Token>>,
+    /// 刷新令牌的锁，同一时间只有一个刷新请求
+    refreshing: Mutex<()>
+}
+
+impl TokenManager {
+    pub fn new(token: Option<Token>) -> TokenManager {
+        TokenManager {
+            token: RwLock::new(token.filter(Token::is_usable)),
+            refreshing: Mutex::new(())
+        }
+    }
+
+    /// 当前的令牌(可能已过期)
+    pub fn current(&self) -> Option<Token> { self.token.read().unwrap().clone() }
+
+    /// 获取可用的令牌，没有可用的令牌时调用`fetch`获取
+    /// 并发调用时只有一个`fetch`执行，其它调用等待并使用其结果
+    pub async fn get<F, Fut>(&self, fetch: F) -> Result<String, AnyError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Token, AnyError>>
+    {
+        if let Some(token) = self.usable() {
+            return Ok(token);
+        }
+        let _refreshing = self.refreshing.lock().await;
+        //等待期间可能已由其它调用刷新
+        if let Some(token) = self.usable() {
+            return Ok(token);
+        }
+        let token = fetch().await?;
+        *self.token.write().unwrap() = Some(token.clone());
+        Ok(token.token)
+    }
+
+    /// 提前刷新令牌，`seen`为调用方看到的令牌，等待期间已由其它调用刷新时不刷新
+    pub async fn refresh<F, Fut>(&self, seen: Option<&str>, fetch: F) -> Result<(), AnyError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Token, AnyError>>
+    {
+        let _refreshing = self.refreshing.lock().await;
+        if self.current().as_ref().map(|token| token.token.as_str()) != seen {
+            return Ok(());
+        }
+        let token = fetch().await?;
+        *self.token.write().unwrap() = Some(token);
+        Ok(())
+    }
+
+    /// 作废令牌(令牌已被其它调用刷新时不作废)
+    pub fn invalidate(&self, token: &str) {
+        let mut current = self.token.write().unwrap();
+        if current.as_ref().map_or(false, |current| current.token == token) {
+            *current = None;
+        }
+    }
+
+    /// 清除令牌
+    pub fn clear(&self) { *self.token.write().unwrap() = None; }
+
+    fn usable(&self) -> Option<String> {
+        self.token
+            .read()
+            .unwrap()
+            .as_ref()
+            .filter(|token| token.is_usable())
+            .map(|token| token.token.to_owned())
+    }
+}
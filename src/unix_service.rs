@@ -0,0 +1,149 @@
+//!
+//! Linux服务(systemd)的实现
+//!
+//! 服务以`Type=notify`方式运行，收到SIGTERM或SIGINT信号时优雅退出
+//!
+
+use super::service::{exit_code, Service};
+use futures::channel::oneshot;
+use signal_hook::{iterator::Signals, SIGINT, SIGTERM};
+use std::{
+    env, error::Error, ffi::OsString, fs, os::unix::{net::UnixDatagram, process::CommandExt}, path::PathBuf, process::Command, thread
+};
+
+/// systemd服务配置文件的目录
+const UNIT_DIR: &'static str = "/etc/systemd/system";
+
+/// 生成systemd服务配置文件
+pub fn unit_file(description: &str, run_args: Vec<OsString>) -> Result<String, Box<dyn Error>> {
+    let exe_path = env::current_exe()?;
+    let mut exec_start = quote(&exe_path.to_string_lossy());
+    for arg in &run_args {
+        exec_start.push(' ');
+        exec_start.push_str(&quote(&arg.to_string_lossy()));
+    }
+    Ok(format!(
+        "[Unit]\n\
+         Description={}\n\
+         After=network.target\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={}\n\
+         WorkingDirectory={}\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         TimeoutStopSec=30\n\
+         \n\
+         [Install]\n\
+         WantedBy=multi-user.target\n",
+        description,
+        exec_start,
+        exe_path.parent().map(|dir| dir.display().to_string()).unwrap_or("/".to_owned())
+    ))
+}
+
+/// 安装systemd服务
+pub fn install(name: &str, description: &str, run_args: Vec<OsString>) -> Result<(), Box<dyn Error>> {
+    let unit_path = unit_path(name);
+    fs::write(&unit_path, unit_file(description, run_args)?)
+        .map_err(|e| format!("写入服务配置文件: {}, 失败: {}", unit_path.display(), e))?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", name])
+}
+
+/// 卸载systemd服务
+pub fn uninstall(name: &str) -> Result<(), Box<dyn Error>> {
+    systemctl(&["disable", "--now", name])?;
+    fs::remove_file(unit_path(name))?;
+    systemctl(&["daemon-reload"])
+}
+
+/// 启动systemd服务
+pub fn start(name: &str) -> Result<(), Box<dyn Error>> { systemctl(&["start", name]) }
+
+/// 停止systemd服务
+pub fn stop(name: &str) -> Result<(), Box<dyn Error>> { systemctl(&["stop", name]) }
+
+/// 运行服务
+pub fn run(srv: &dyn Service) -> Result<(), Box<dyn Error>> {
+    //由systemd启动时会设置NOTIFY_SOCKET环境变量
+    let from_systemd = env::var_os("NOTIFY_SOCKET").is_some();
+    if let Err(e) = srv.initialize(from_systemd) {
+        if log_enabled!(log::Level::Error) {
+            error!("initial error: {:?}", e);
+        } else {
+            eprintln!("initial error: {:?}", e);
+        }
+        return Err(e);
+    }
+
+    info!("run");
+
+    //收到SIGTERM或SIGINT信号时发送停止信号
+    let (tx, rx) = oneshot::channel::<()>();
+    let signals = Signals::new(&[SIGTERM, SIGINT])?;
+    thread::Builder::new().name("srv-signal".to_owned()).spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!("signal {} received", signal);
+            notify("STOPPING=1");
+            let _ = tx.send(());
+        }
+    })?;
+
+    match srv.main(Some(rx)) {
+        Ok(exit_code) => {
+            if exit_code == exit_code::RESTART {
+                //使用相同的参数替换当前进程
+                info!("restarting");
+                let e = Command::new(env::current_exe()?).args(env::args_os().skip(1)).exec();
+                warn!("restart failed, error: {}", e);
+            } else {
+                info!("stop");
+            }
+        },
+        Err(e) => {
+            error!("abnormal terminated, error: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// 通知systemd服务已就绪
+pub fn notify_ready() { notify("READY=1") }
+
+/// 向systemd发送状态通知(sd_notify)
+fn notify(state: &str) {
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return
+    };
+    //不支持抽象命名空间的地址
+    if path.starts_with('@') {
+        warn!("sd_notify {}, unsupported socket: {}", state, path);
+        return;
+    }
+    if let Err(e) = UnixDatagram::unbound().and_then(|sock| sock.send_to(state.as_bytes(), &path)) {
+        warn!("sd_notify {} failed: {}", state, e);
+    }
+}
+
+fn unit_path(name: &str) -> PathBuf { PathBuf::from(UNIT_DIR).join(format!("{}.service", name)) }
+
+fn systemctl(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("systemctl").args(args).status()?;
+    if !status.success() {
+        return Err(format!("systemctl {} failed: {}", args.join(" "), status).into());
+    }
+    Ok(())
+}
+
+/// 参数包含空白字符时加上引号
+fn quote(arg: &str) -> String {
+    if arg.chars().any(|c| c.is_whitespace() || c == '"') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg.to_owned()
+    }
+}
@@ -1,9 +1,8 @@
 //!
-//! Windows服务接口封装
-//!
-//! 实现WinService接口即可
+//! Windows服务(SCM)的实现
 //!
 
+use super::service::{exit_code, Service};
 use futures::channel::oneshot;
 use std::{
     env, error::Error, ffi::OsString, mem::transmute, process::Command, sync::{Arc, Mutex}, thread, time
@@ -16,146 +15,130 @@ const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
 
 windows_service::define_windows_service!(ffi_service_main, service_main);
 
-static mut SRV_REGISTERED: Option<&'static (dyn WinService + Sync)> = None;
+static mut SRV_REGISTERED: Option<&'static dyn Service> = None;
 
-/// 退出代码
-pub mod exit_code {
-    pub const OK: u16 = 0;
-    pub const RESTART: u16 = 3010; //ERROR_SUCCESS_REBOOT_REQUIRED
+/// 安装Windows服务
+pub fn install(name: &str, description: &str, run_args: Vec<OsString>) -> Result<(), Box<dyn Error>> {
+    let mgr = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE
+    )?;
+    let info = ServiceInfo {
+        name: name.into(),
+        display_name: description.into(),
+        service_type: SERVICE_TYPE,
+        #[cfg(debug_assertions)]
+        start_type: ServiceStartType::OnDemand,
+        #[cfg(not(debug_assertions))]
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: env::current_exe()?,
+        launch_arguments: run_args,
+        dependencies: vec![],
+        account_name: None,
+        account_password: None
+    };
+    let srv = mgr.create_service(&info, ServiceAccess::START | ServiceAccess::CHANGE_CONFIG)?;
+    //开启故障自动重启
+    let failure_actions = ServiceFailureActions {
+        //[在此时间之后重置失败计数]
+        reset_period: ServiceFailureResetPeriod::After(time::Duration::from_secs(86400)),
+        reboot_msg: None,
+        command: None,
+        actions: Some(vec![
+            //[第一次失败]
+            ServiceAction {
+                action_type: ServiceActionType::Restart,
+                delay: time::Duration::from_secs(5)
+            },
+            //[第二次失败]
+            ServiceAction {
+                action_type: ServiceActionType::Restart,
+                delay: time::Duration::from_secs(15)
+            },
+            //[后续失败]
+            ServiceAction {
+                action_type: ServiceActionType::None,
+                delay: Default::default()
+            },
+        ])
+    };
+    srv.update_failure_actions(failure_actions)?;
+    //[启用发生错误时]
+    //退出代码不为0时也触发故障重启机制
+    //srv.set_failure_actions_on_non_crash_failures(true)?;
+    Ok(())
 }
 
-/// 服务接口
-pub trait WinService: Sync {
-    /// 服务名称
-    fn name(&self) -> &str;
-
-    /// 服务描述
-    fn description(&self) -> &str { self.name() }
-
-    /// 初始化
-    fn initialize(&self, from_scm: bool) -> Result<(), Box<dyn Error>>;
-    /// 服务入口过程
-    fn main(&self, stop_signer: Option<oneshot::Receiver<()>>) -> Result<u16, Box<dyn Error>>;
-
-    /// 安装Windows服务
-    fn install(&self, run_args: Vec<OsString>) -> Result<(), Box<dyn Error>> {
-        let mgr = ServiceManager::local_computer(
-            None::<&str>,
-            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE
-        )?;
-        let info = ServiceInfo {
-            name: self.name().into(),
-            display_name: self.description().into(),
-            service_type: SERVICE_TYPE,
-            #[cfg(debug_assertions)]
-            start_type: ServiceStartType::OnDemand,
-            #[cfg(not(debug_assertions))]
-            start_type: ServiceStartType::AutoStart,
-            error_control: ServiceErrorControl::Normal,
-            executable_path: env::current_exe()?,
-            launch_arguments: run_args,
-            dependencies: vec![],
-            account_name: None,
-            account_password: None
-        };
-        let srv = mgr.create_service(&info, ServiceAccess::START | ServiceAccess::CHANGE_CONFIG)?;
-        //开启故障自动重启
-        let failure_actions = ServiceFailureActions {
-            //[在此时间之后重置失败计数]
-            reset_period: ServiceFailureResetPeriod::After(time::Duration::from_secs(86400)),
-            reboot_msg: None,
-            command: None,
-            actions: Some(vec![
-                //[第一次失败]
-                ServiceAction {
-                    action_type: ServiceActionType::Restart,
-                    delay: time::Duration::from_secs(5)
-                },
-                //[第二次失败]
-                ServiceAction {
-                    action_type: ServiceActionType::Restart,
-                    delay: time::Duration::from_secs(15)
-                },
-                //[后续失败]
-                ServiceAction {
-                    action_type: ServiceActionType::None,
-                    delay: Default::default()
-                },
-            ])
-        };
-        srv.update_failure_actions(failure_actions)?;
-        //[启用发生错误时]
-        //退出代码不为0时也触发故障重启机制
-        //srv.set_failure_actions_on_non_crash_failures(true)?;
-        Ok(())
-    }
-
-    /// 卸载Windows服务
-    fn uninstall(&self) -> Result<(), Box<dyn Error>> {
-        let mgr = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
-        let srv = mgr.open_service(
-            self.name(),
-            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE
-        )?;
-        if srv.query_status()?.current_state != ServiceState::Stopped {
-            srv.stop()?;
-            while srv.query_status()?.current_state != ServiceState::Stopped {
-                thread::sleep(time::Duration::from_secs(1));
-            }
+/// 卸载Windows服务
+pub fn uninstall(name: &str) -> Result<(), Box<dyn Error>> {
+    let mgr = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let srv =
+        mgr.open_service(name, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)?;
+    if srv.query_status()?.current_state != ServiceState::Stopped {
+        srv.stop()?;
+        while srv.query_status()?.current_state != ServiceState::Stopped {
+            thread::sleep(time::Duration::from_secs(1));
         }
-        srv.delete()?;
-        Ok(())
     }
+    srv.delete()?;
+    Ok(())
+}
 
-    /// 运行Windows服务
-    fn run(&self) -> Result<(), Box<dyn Error>>
-    where
-        Self: Sized
-    {
-        //挂载Windows服务
-        unsafe {
-            SRV_REGISTERED = Some(transmute(self as &dyn WinService));
-        }
-        if let Err(e) = service_dispatcher::start(self.name(), ffi_service_main) {
-            if let windows_service::Error::Winapi(ref e) = e {
-                match e.raw_os_error() {
-                    //非SCM调用则直接运行服务
-                    Some(code) if code == 1063 => return run_service(false),
-                    _ => {}
-                }
+/// 运行Windows服务
+pub fn run(srv: &dyn Service) -> Result<(), Box<dyn Error>> {
+    //挂载Windows服务
+    unsafe {
+        SRV_REGISTERED = Some(transmute(srv));
+    }
+    if let Err(e) = service_dispatcher::start(srv.name(), ffi_service_main) {
+        if let windows_service::Error::Winapi(ref e) = e {
+            match e.raw_os_error() {
+                //非SCM调用则直接运行服务
+                Some(code) if code == 1063 => return run_service(false),
+                _ => {}
             }
-            return Err(e.into());
         }
-        Ok(())
+        return Err(e.into());
     }
+    Ok(())
+}
 
-    /// 启动Windows服务
-    fn start(&self) -> Result<(), Box<dyn Error>> {
-        let mgr = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
-        let srv = mgr.open_service(self.name(), ServiceAccess::QUERY_STATUS | ServiceAccess::START)?;
-        if srv.query_status()?.current_state != ServiceState::Running {
-            srv.start(&[""])?;
-            while srv.query_status()?.current_state != ServiceState::Running {
-                thread::sleep(time::Duration::from_secs(1));
-            }
+/// 启动Windows服务
+pub fn start(name: &str) -> Result<(), Box<dyn Error>> {
+    let mgr = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let srv = mgr.open_service(name, ServiceAccess::QUERY_STATUS | ServiceAccess::START)?;
+    if srv.query_status()?.current_state != ServiceState::Running {
+        srv.start(&[""])?;
+        while srv.query_status()?.current_state != ServiceState::Running {
+            thread::sleep(time::Duration::from_secs(1));
         }
-        Ok(())
     }
+    Ok(())
+}
 
-    /// 停止Windows服务
-    fn stop(&self) -> Result<(), Box<dyn Error>> {
-        let mgr = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
-        let srv = mgr.open_service(self.name(), ServiceAccess::QUERY_STATUS | ServiceAccess::STOP)?;
-        if srv.query_status()?.current_state != ServiceState::Stopped {
-            srv.stop()?;
-            while srv.query_status()?.current_state != ServiceState::Stopped {
-                thread::sleep(time::Duration::from_secs(1));
-            }
+/// 停止Windows服务
+pub fn stop(name: &str) -> Result<(), Box<dyn Error>> {
+    let mgr = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let srv = mgr.open_service(name, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP)?;
+    if srv.query_status()?.current_state != ServiceState::Stopped {
+        srv.stop()?;
+        while srv.query_status()?.current_state != ServiceState::Stopped {
+            thread::sleep(time::Duration::from_secs(1));
         }
-        Ok(())
     }
+    Ok(())
 }
 
+/// Windows下不支持systemd服务配置文件
+pub fn unit_file(_description: &str, _run_args: Vec<OsString>) -> Result<String, Box<dyn Error>> {
+    Err("仅Linux支持systemd服务配置文件".into())
+}
+
+/// 通知SCM服务已就绪
+/// (SCM托管时在进入服务入口过程前已报告运行状态)
+pub fn notify_ready() {}
+
 /// Windows服务入口(运行时独立的线程中)
 fn service_main(_: Vec<OsString>) {
     //为了使日志输出的线程名称不为"unnamed",创建一个"srv-main"名称的线程来运行
@@ -218,7 +201,7 @@ fn run_service(from_scm: bool) -> Result<(), Box<dyn Error>> {
 }
 
 /// Windows服务开始(由SCM调用)
-fn scm_serve(srv: &dyn WinService) -> Result<u16, Box<dyn Error>> {
+fn scm_serve(srv: &dyn Service) -> Result<u16, Box<dyn Error>> {
     //服务状态汇报的句柄
     let status = Arc::new(Mutex::new(None));
 
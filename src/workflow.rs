@@ -0,0 +1,91 @@
+//!
+//! 工作项状态流转规则
+//!
+//! 提交日志中的关键字(如`#fix CD-7`)按工作项类型映射到目标状态，
+//! 只有当前状态在允许的来源状态中才修改
+//!
+
+use super::*;
+use serde::Deserialize;
+
+/// `#编号`使用的默认关键字
+const DEFAULT_KEYWORD: &'static str = "done";
+
+/// 状态流转
+#[derive(Debug, Clone, Deserialize)]
+pub struct Transition {
+    /// 触发的关键字(不区分大小写)
+    pub keywords: Vec<String>,
+    /// 适用的工作项类型(epic, feature, story, task, bug, issue)，为空时适用于全部类型
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// 允许的当前状态，为空时不限制
+    #[serde(default)]
+    pub from: Vec<String>,
+    /// 目标状态
+    pub to: String
+}
+
+/// 状态流转规则
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Workflow {
+    /// `#编号`使用的关键字
+    pub default_keyword: String,
+    /// 按顺序匹配，使用第一个关键字和类型都匹配的规则
+    pub transitions: Vec<Transition>
+}
+
+impl Default for Workflow {
+    fn default() -> Self {
+        Workflow {
+            default_keyword: DEFAULT_KEYWORD.to_owned(),
+            transitions: Vec::new()
+        }
+    }
+}
+
+impl Workflow {
+    /// 加载`[workflow]`配置，未配置规则时将新建或进行中的工作项修改为已完成
+    pub fn load(cfg: &settings::SharedConfig) -> Result<Workflow, AnyError> {
+        let mut workflow: Workflow = cfg.config_or_default("workflow")?;
+        if workflow.transitions.is_empty() {
+            workflow.transitions.push(Transition {
+                keywords: vec![DEFAULT_KEYWORD.to_owned()],
+                types: Vec::new(),
+                from: vec!["新建".to_owned(), "进行中".to_owned()],
+                to: "已完成".to_owned()
+            });
+        }
+        Ok(workflow)
+    }
+
+    /// 是否为状态流转的关键字
+    pub fn has_keyword(&self, keyword: &str) -> bool {
+        keyword.is_empty() || self.transitions.iter().any(|transition| transition.matches_keyword(keyword))
+    }
+
+    /// 查找关键字和工作项类型对应的状态流转，关键字为空时使用默认关键字
+    pub fn transition(&self, keyword: &str, item_type: &str) -> Option<&Transition> {
+        let keyword = if keyword.is_empty() {
+            self.default_keyword.as_str()
+        } else {
+            keyword
+        };
+        self.transitions.iter().find(|transition| {
+            transition.matches_keyword(keyword) &&
+                (transition.types.is_empty() || transition.types.iter().any(|ty| ty == item_type))
+        })
+    }
+}
+
+impl Transition {
+    fn matches_keyword(&self, keyword: &str) -> bool {
+        self.keywords.iter().any(|kw| kw.eq_ignore_ascii_case(keyword))
+    }
+
+    /// 当前状态是否允许流转
+    pub fn allows(&self, state: &str) -> bool {
+        self.from.is_empty() || self.from.iter().any(|from| from == state)
+    }
+}
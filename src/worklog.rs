@@ -0,0 +1,85 @@
+//!
+//! 工作项工时登记
+//!
+//! 提交日志中的`#time 1h30m`或`工时:2h`登记到对应的工作项，
+//! 同一工作项的多条工时合并为一条，单次提交的工时合计不能超过上限
+//!
+
+use super::*;
+use serde::Deserialize;
+
+/// 单次提交的工时上限(分钟)
+const DEFAULT_MAX_MINUTES: u64 = 16 * 60;
+
+/// 工时登记的配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WorklogConfig {
+    /// 是否登记工时
+    pub enabled: bool,
+    /// 单次提交的工时上限(分钟)
+    pub max_minutes: u64
+}
+
+impl Default for WorklogConfig {
+    fn default() -> Self {
+        WorklogConfig {
+            enabled: true,
+            max_minutes: DEFAULT_MAX_MINUTES
+        }
+    }
+}
+
+/// 工作项的工时
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worklog {
+    pub id: String,
+    pub minutes: u64,
+    /// 工时说明(多条时按行合并)
+    pub comment: String
+}
+
+impl WorklogConfig {
+    /// 按工作项合并提交日志中的工时，并检查工时上限
+    pub fn collect(&self, commands: &smart_commit::Commands) -> Result<Vec<Worklog>, String> {
+        let mut worklogs: Vec<Worklog> = Vec::new();
+        for (id, minutes, comment) in commands.worklogs() {
+            match worklogs.iter_mut().find(|worklog| worklog.id == id) {
+                Some(worklog) => {
+                    worklog.minutes += minutes;
+                    if !comment.is_empty() {
+                        if !worklog.comment.is_empty() {
+                            worklog.comment.push('\n');
+                        }
+                        worklog.comment.push_str(comment);
+                    }
+                },
+                None => {
+                    worklogs.push(Worklog {
+                        id: id.to_owned(),
+                        minutes,
+                        comment: comment.to_owned()
+                    })
+                },
+            }
+        }
+        let total: u64 = worklogs.iter().map(|worklog| worklog.minutes).sum();
+        if total > self.max_minutes {
+            return Err(format!(
+                "登记的工时合计{}超过了单次提交的上限{}",
+                format_minutes(total),
+                format_minutes(self.max_minutes)
+            ));
+        }
+        Ok(worklogs)
+    }
+}
+
+/// 显示工时，如: 1h30m
+pub fn format_minutes(minutes: u64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m)
+    }
+}
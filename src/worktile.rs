@@ -4,11 +4,11 @@
 //!
 
 #![allow(dead_code)]
-use super::AnyError;
-use serde::{de::DeserializeOwned, Deserialize};
+use super::{cache, comment, members, smart_commit, token, workflow, worklog, AnyError};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json as json;
 use std::{
-    collections::HashMap, sync::{Arc, RwLock}
+    collections::{BTreeMap, HashMap, HashSet}, fmt, sync::{Arc, RwLock}, time::{Duration, Instant}
 };
 
 const DEFAULT_API_URL: &'static str = "https://open.worktile.com";
@@ -17,7 +17,13 @@ pub struct ClientBuilder {
     api_url: String,
     product_name: Option<String>,
     id: Option<String>,
-    key: Option<String>
+    key: Option<String>,
+    workflow: workflow::Workflow,
+    comment: comment::CommentConfig,
+    worklog: worklog::WorklogConfig,
+    members: members::MemberMapping,
+    error_codes: HashMap<String, ErrorCategory>,
+    cache_path: Option<String>
 }
 
 impl ClientBuilder {
@@ -26,7 +32,13 @@ impl ClientBuilder {
             api_url: DEFAULT_API_URL.to_owned(),
             product_name: None,
             id: None,
-            key: None
+            key: None,
+            workflow: Default::default(),
+            comment: Default::default(),
+            worklog: Default::default(),
+            members: Default::default(),
+            error_codes: Default::default(),
+            cache_path: None
         }
     }
     pub fn api_url(mut self, url: impl Into<String>) -> Self {
@@ -42,6 +54,32 @@ impl ClientBuilder {
         self.key = Some(key.into());
         self
     }
+    pub fn workflow(mut self, workflow: workflow::Workflow) -> Self {
+        self.workflow = workflow;
+        self
+    }
+    pub fn comment(mut self, comment: comment::CommentConfig) -> Self {
+        self.comment = comment;
+        self
+    }
+    pub fn worklog(mut self, worklog: worklog::WorklogConfig) -> Self {
+        self.worklog = worklog;
+        self
+    }
+    pub fn members(mut self, members: members::MemberMapping) -> Self {
+        self.members = members;
+        self
+    }
+    /// 错误代码的分类，优先于内置的已知错误代码
+    pub fn error_codes(mut self, error_codes: HashMap<String, ErrorCategory>) -> Self {
+        self.error_codes = error_codes;
+        self
+    }
+    /// 对象ID的缓存文件路径，未指定时只缓存在内存中
+    pub fn cache_path(mut self, path: impl Into<String>) -> Self {
+        self.cache_path = Some(path.into());
+        self
+    }
     pub fn build(self) -> Result<Client, AnyError> {
         if self.api_url.is_empty() {
             return Err("API地址为空".into());
@@ -54,13 +92,20 @@ impl ClientBuilder {
             (Some(id), Some(key)) if !id.is_empty() && !key.is_empty() => (id, key),
             _ => return Err("API认证信息为空".into())
         };
+        let (ctx, cached_token) = Context::open(self.cache_path, &product_name)?;
         Ok(Client {
             client: reqwest::ClientBuilder::new().danger_accept_invalid_certs(true).no_proxy().build()?,
             api_url: self.api_url,
             product_name,
             id,
             key,
-            ctx: Arc::new(RwLock::new(Context::new()))
+            workflow: Arc::new(self.workflow),
+            comment: Arc::new(self.comment),
+            worklog: Arc::new(self.worklog),
+            members: Arc::new(self.members),
+            error_codes: Arc::new(self.error_codes),
+            tokens: Arc::new(token::TokenManager::new(cached_token)),
+            ctx: Arc::new(RwLock::new(ctx))
         })
     }
 }
@@ -75,35 +120,114 @@ pub struct Client {
     id: String,
     /// CLIENT_SECRET
     key: String,
+    /// 工作项状态流转规则
+    workflow: Arc<workflow::Workflow>,
+    /// 工作项评论的配置
+    comment: Arc<comment::CommentConfig>,
+    /// 工时登记的配置
+    worklog: Arc<worklog::WorklogConfig>,
+    /// 提交者与成员的映射
+    members: Arc<members::MemberMapping>,
+    /// 配置的错误代码分类
+    error_codes: Arc<HashMap<String, ErrorCategory>>,
+    /// 访问令牌
+    tokens: Arc<token::TokenManager>,
     /// 接口的上下文信息
     ctx: Arc<RwLock<Context>>
 }
 
 /// 接口的上下文信息
 struct Context {
-    /// 访问令牌
-    access_token: Option<AccessToken>,
-    /// 代码托管平台的ID
-    product_id: Option<String>,
-    /// 代码托管平台的用户ID列表
-    users: HashMap<String, String>,
-    /// 代码仓库的ID列表
-    repositories: HashMap<String, String>,
-    /// 代码仓库的分支ID列表
-    branches: HashMap<(String, String), String>,
-    /// 工作项状态Id
-    states: HashMap<String, String>
+    /// 代码托管平台、用户、仓库、分支、标签和状态的ID
+    ids: HashMap<cache::Key, String>,
+    /// 提交者对应的成员(未找到时为`None`)和查找的时间
+    members: HashMap<String, (Option<Member>, Instant)>,
+    /// 未找到对应成员的提交者
+    unmapped: BTreeMap<String, members::UnmappedAuthor>,
+    /// 未找到对应成员的提交者的提交SHA值，重试和重新放入队列时不重复计数，找到成员后删除
+    unmapped_commits: HashMap<String, HashSet<String>>,
+    /// ID的缓存文件
+    cache: Option<cache::IdCache>
 }
 
 impl Context {
-    fn new() -> Context {
-        Context {
-            access_token: None,
-            product_id: None,
-            users: HashMap::new(),
-            repositories: HashMap::new(),
-            branches: HashMap::new(),
-            states: HashMap::new()
+    /// 创建上下文，指定缓存文件时加载缓存的ID，同时返回缓存的访问令牌
+    fn open(
+        cache_path: Option<String>,
+        product_name: &str
+    ) -> Result<(Context, Option<token::Token>), AnyError> {
+        let mut token = None;
+        let mut ctx = Context {
+            ids: HashMap::new(),
+            members: HashMap::new(),
+            unmapped: BTreeMap::new(),
+            unmapped_commits: HashMap::new(),
+            cache: None
+        };
+        if let Some(path) = cache_path {
+            let (cache, ids, cached_token) = cache::IdCache::open(path, product_name)?;
+            ctx.ids = ids;
+            ctx.cache = Some(cache);
+            token = cached_token.map(|cached| {
+                token::Token {
+                    token: cached.token,
+                    expires_in: cached.expires_in,
+                    issued_at: cached.issued_at
+                }
+            });
+        }
+        Ok((ctx, token))
+    }
+
+    /// 查询缓存的ID
+    fn id(&self, key: &cache::Key) -> Option<String> { self.ids.get(key).cloned() }
+
+    /// 缓存ID，写入缓存文件失败时只记录日志
+    fn put_id(&mut self, key: cache::Key, id: &str) {
+        if let Some(cache) = &mut self.cache {
+            if let Err(e) = cache.put(&key, id) {
+                warn!("cache {} failed: {}", key, e);
+            }
+        }
+        self.ids.insert(key, id.to_owned());
+    }
+
+    /// 请求地址返回404时删除地址中最后一个缓存的ID及其下级对象的ID，返回是否有删除
+    /// 只匹配完整的路径段，请求内容中的ID和地址中其它对象的ID不受影响
+    fn invalidate(&mut self, uri: &str) -> bool {
+        let path = uri.split('?').next().unwrap_or_default();
+        let stale = path.split('/').rev().find_map(|segment| {
+            self.ids.iter().find(|(_, id)| id.as_str() == segment).map(|(key, _)| key.clone())
+        });
+        let stale = match stale {
+            Some(stale) => stale,
+            None => return false
+        };
+        let keys: Vec<cache::Key> =
+            self.ids.keys().filter(|key| **key == stale || key.belongs_to(&stale)).cloned().collect();
+        for key in &keys {
+            warn!("cached {} is not found, invalidated", key);
+            if let Some(cache) = &mut self.cache {
+                if let Err(e) = cache.remove(key) {
+                    warn!("invalidate {} failed: {}", key, e);
+                }
+            }
+            self.ids.remove(key);
+        }
+        true
+    }
+
+    /// 保存访问令牌到缓存文件
+    fn put_token(&mut self, token: &token::Token) {
+        if let Some(cache) = &mut self.cache {
+            let cached = cache::CachedToken {
+                token: token.token.to_owned(),
+                expires_in: token.expires_in,
+                issued_at: token.issued_at
+            };
+            if let Err(e) = cache.token(&cached) {
+                warn!("cache access token failed: {}", e);
+            }
         }
     }
 }
@@ -117,8 +241,11 @@ struct AccessToken {
     expires_in: chrono::NaiveDateTime
 }
 
-/// 令牌刷新的时间阈值(小时)
-const TOKEN_REFRESH_THRESOLD: i64 = 12;
+/// 后台刷新令牌的最小间隔(秒)
+const TOKEN_REFRESH_INTERVAL_SECS: u64 = 60;
+
+/// 未找到对应成员的提交者重新查找的间隔(秒)，成员后来加入Worktile时不需要重启服务
+const UNMAPPED_RETRY_SECS: u64 = 600;
 
 /// 提取响应数据的[Id]值
 #[derive(Deserialize)]
@@ -126,94 +253,473 @@ struct ExtractId {
     id: String
 }
 
-/// 提取响应数据的[Id]列表
+/// 提取列表数据的[Id]和名称
 #[derive(Deserialize)]
-struct ExtractIds {
-    values: Vec<ExtractId>
+struct NamedId {
+    id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    full_name: Option<String>
 }
 
-/// 工作项状态
+impl NamedId {
+    fn name_is(&self, name: &str) -> bool { self.name.as_ref().map_or(false, |value| value == name) }
+}
+
+/// 分页列表
 #[derive(Deserialize)]
-struct WorkItemState {
-    id: String,
-    name: String
+struct Page<T> {
+    values: Vec<T>,
+    #[serde(default)]
+    page_size: u64,
+    #[serde(default)]
+    total: u64
+}
+
+/// 分页查询的每页数量
+const PAGE_SIZE: u64 = 100;
+
+/// 分页查询的最大页数
+const MAX_PAGES: u64 = 100;
+
+/// 工作项状态
+#[derive(Debug, Deserialize)]
+pub struct WorkItemState {
+    pub id: String,
+    pub name: String
 }
 
 /// 工作项信息
-#[derive(Deserialize)]
-struct WorkItem {
-    id: String,
+#[derive(Debug, Deserialize)]
+pub struct WorkItem {
+    pub id: String,
+    /// 编号，如: CD-7
+    #[serde(default)]
+    pub identifier: String,
     #[serde(rename = "type")]
-    item_type: String,
-    state: WorkItemState
+    pub item_type: String,
+    pub state: WorkItemState
+}
+
+/// 企业成员
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Member {
+    pub uid: String,
+    /// 用户名
+    pub name: String,
+    /// 显示名
+    pub display_name: String,
+    #[serde(default)]
+    pub email: String
+}
+
+/// 提交者(代码托管平台的用户)
+#[derive(Debug, Clone)]
+struct Committer {
+    /// 用户名，映射到成员时为成员的用户名
+    name: String,
+    /// 显示名，映射到成员时为成员的显示名
+    display_name: String,
+    member: Option<Member>
+}
+
+/// 错误分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// 对象不存在
+    NotFound,
+    /// 没有权限
+    Forbidden,
+    /// 请求过于频繁
+    RateLimited,
+    /// 认证失败
+    Auth,
+    /// 请求参数错误
+    Invalid,
+    /// 服务器错误
+    Server,
+    /// 网络错误
+    Network,
+    /// 其它错误
+    Unknown
+}
+
+/// 已知的Worktile错误代码
+/// 其它错误代码可以在`[worktile.error_codes]`中配置分类，未配置时按HTTP状态码分类
+const KNOWN_CODES: &[(&'static str, ErrorCategory, &'static str)] = &[
+    ("100026", ErrorCategory::Auth, "'access_token'无效"),
+    ("100028", ErrorCategory::Auth, "'access_token'已失效"),
+    ("100032", ErrorCategory::Auth, "'authorization_code'鉴权失败")
+];
+
+/// 错误代码的分类，先查找配置的分类，再查找已知的错误代码，都没有时为`Unknown`
+pub fn code_category(code: &str, configured: &HashMap<String, ErrorCategory>) -> ErrorCategory {
+    configured.get(code).copied().unwrap_or_else(|| {
+        KNOWN_CODES
+            .iter()
+            .find(|(known, ..)| *known == code)
+            .map(|(_, category, _)| *category)
+            .unwrap_or(ErrorCategory::Unknown)
+    })
+}
+
+/// 接口返回的错误，`request`为请求的方法和地址(如: GET v1/scm/products)
+#[derive(Debug)]
+pub enum ApiError {
+    /// 网络错误(连接失败、超时等)
+    Transport {
+        request: String,
+        source: reqwest::Error
+    },
+    /// HTTP状态码错误
+    Status {
+        request: String,
+        status: u16,
+        message: String
+    },
+    /// 接口返回的错误代码，`category`为错误代码的分类
+    Code {
+        request: String,
+        status: u16,
+        code: String,
+        category: ErrorCategory,
+        message: String
+    },
+    /// 响应数据解析失败
+    Decode {
+        request: String,
+        message: String
+    },
+    /// 认证失败(重新获取令牌后仍然失败)
+    Auth {
+        request: String,
+        message: String
+    },
+    /// 请求中使用的缓存ID已不存在(已从缓存删除，重试时重新查询)
+    Stale {
+        request: String
+    }
+}
+
+impl ApiError {
+    /// 错误分类
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            ApiError::Transport {
+                ..
+            } => ErrorCategory::Network,
+            ApiError::Status {
+                status,
+                ..
+            } => status_category(*status),
+            ApiError::Code {
+                status,
+                category,
+                ..
+            } => {
+                match category {
+                    ErrorCategory::Unknown => status_category(*status),
+                    category => *category
+                }
+            },
+            ApiError::Decode {
+                ..
+            } => ErrorCategory::Unknown,
+            ApiError::Auth {
+                ..
+            } => ErrorCategory::Auth,
+            ApiError::Stale {
+                ..
+            } => ErrorCategory::NotFound
+        }
+    }
+
+    /// 接口返回的错误代码
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ApiError::Code {
+                code,
+                ..
+            } => Some(code),
+            _ => None
+        }
+    }
+
+    /// 请求的方法和地址
+    pub fn request(&self) -> &str {
+        match self {
+            ApiError::Transport {
+                request,
+                ..
+            } |
+            ApiError::Status {
+                request,
+                ..
+            } |
+            ApiError::Code {
+                request,
+                ..
+            } |
+            ApiError::Decode {
+                request,
+                ..
+            } |
+            ApiError::Auth {
+                request,
+                ..
+            } |
+            ApiError::Stale {
+                request
+            } => request
+        }
+    }
+}
+
+/// HTTP状态码的分类
+fn status_category(status: u16) -> ErrorCategory {
+    match status {
+        401 => ErrorCategory::Auth,
+        403 => ErrorCategory::Forbidden,
+        404 => ErrorCategory::NotFound,
+        429 => ErrorCategory::RateLimited,
+        400..=499 => ErrorCategory::Invalid,
+        500..=599 => ErrorCategory::Server,
+        _ => ErrorCategory::Unknown
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::Transport {
+                request,
+                source
+            } => write!(f, "Worktile API transport error: {} ({})", source, request),
+            ApiError::Status {
+                request,
+                status,
+                message
+            } => write!(f, "Worktile API HTTP status: {} {} ({})", status, message, request),
+            ApiError::Code {
+                request,
+                code,
+                message,
+                ..
+            } => {
+                match KNOWN_CODES.iter().find(|(known, ..)| known == code) {
+                    Some((_, _, desc)) => {
+                        write!(f, "Worktile API error code: {} {}, {} ({})", code, desc, message, request)
+                    },
+                    None => write!(f, "Worktile API error code: {}, {} ({})", code, message, request)
+                }
+            },
+            ApiError::Decode {
+                request,
+                message
+            } => write!(f, "Worktile API response decode failed: {} ({})", message, request),
+            ApiError::Auth {
+                request,
+                message
+            } => write!(f, "Worktile API authorization failed: {} ({})", message, request),
+            ApiError::Stale {
+                request
+            } => write!(f, "Worktile API object not found, cached ids invalidated ({})", request)
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Transport {
+                source,
+                ..
+            } => Some(source),
+            _ => None
+        }
+    }
 }
 
 /// 提交信息的元数据
 #[derive(Debug)]
 pub struct CommitMeta {
-    pub sha: String,
+    /// 显示的版本(如r5)
+    pub revision: String,
     pub message: String,
     pub committer_name: String,
     pub committed_at: chrono::NaiveDateTime,
+    /// 提交影响的分支
+    pub branches: Vec<CommitBranch>
+}
+
+/// 提交在分支上的元数据
+#[derive(Debug)]
+pub struct CommitBranch {
+    /// 代码仓库名称
+    pub repo: String,
+    /// 分支名称(标签时为标签名称)
+    pub name: String,
+    /// 是否为标签
+    pub tag: bool,
+    pub sha: String,
+    /// 只创建引用(提交已存在，如推送Git标签)
+    pub ref_only: bool,
     pub files_added: Vec<String>,
     pub files_removed: Vec<String>,
     pub files_modified: Vec<String>
 }
 
+/// 提交的同步进度
+/// 同步中断后重新同步时跳过已完成的步骤
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommitProgress {
+    /// 已创建的提交(SHA => Worktile的提交ID)
+    pub commits: BTreeMap<String, String>,
+    /// 已创建引用的提交SHA
+    pub refs: Vec<String>,
+    /// 已完成状态修改的工作项
+    pub finished: Vec<String>,
+    /// 已添加评论的工作项
+    pub commented: Vec<String>,
+    /// 已登记工时的工作项
+    pub logged: Vec<String>,
+    /// 不存在的工作项(跳过评论、工时和状态修改)
+    pub missing: Vec<String>
+}
+
 impl Client {
     pub fn build() -> ClientBuilder { ClientBuilder::new() }
 
     /// 提交代码
-    pub async fn commit(
-        &self,
-        repo: impl AsRef<str>,
-        branch: impl AsRef<str>,
-        meta: CommitMeta
-    ) -> Result<(), AnyError> {
+    /// 在每个受影响的分支上创建提交和引用，每完成一个步骤更新一次同步进度
+    pub async fn commit(&self, meta: CommitMeta, progress: &mut CommitProgress) -> Result<(), AnyError> {
         let prod_id = self.product_id().await?;
-        let repo_id = self.repository_id(repo.as_ref()).await?;
-        let branch_id = self.branch_id(repo.as_ref(), branch.as_ref()).await?;
-        let mut commit_ids = identifiers_from_message(&meta.message)?;
+        let commands = smart_commit::parse(&meta.message);
 
         //确保用户存在于Worktile
-        let _user_id = self.user_id(&meta.committer_name).await?;
+        //SHA值包含仓库的UUID，不同仓库的相同版本号分别计数
+        let sha = meta.branches.first().map_or(meta.revision.as_str(), |branch| branch.sha.as_str());
+        let committer = self.committer(&meta.committer_name, &meta.revision, sha).await?;
+        let _user_id = self.user_id(&committer).await?;
 
-        let mut identifiers = commit_ids.0.clone();
-        identifiers.append(&mut commit_ids.1);
+        let identifiers = commands.identifiers();
 
-        //创建提交
-        let _: ExtractId = self
-            .http_post(
-                "v1/scm/commits",
-                json::json!({
-                    "sha": meta.sha,
-                    "message": meta.message,
-                    "committer_name": meta.committer_name,
-                    "committed_at": meta.committed_at.timestamp(),
-                    "tree_id": tree_id(&repo_id,&branch_id)?,
-                    "files_added": meta.files_added,
-                    "files_removed": meta.files_removed,
-                    "files_modified": meta.files_modified,
-                    "work_item_identifiers": identifiers
-                })
-            )
-            .await?;
+        for branch in &meta.branches {
+            let repo_id = self.repository_id(&branch.repo).await?;
+            let (meta_type, branch_id) = if branch.tag {
+                ("tag", self.tag_id(&branch.repo, &branch.name).await?)
+            } else {
+                ("branch", self.branch_id(&branch.repo, &branch.name).await?)
+            };
+
+            //创建提交
+            if !branch.ref_only && !progress.commits.contains_key(&branch.sha) {
+                let commit: ExtractId = self
+                    .http_post(
+                        "v1/scm/commits",
+                        json::json!({
+                            "sha": branch.sha,
+                            "message": meta.message,
+                            "committer_name": committer.name,
+                            "committed_at": meta.committed_at.timestamp(),
+                            "tree_id": tree_id(&repo_id,&branch_id)?,
+                            "files_added": branch.files_added,
+                            "files_removed": branch.files_removed,
+                            "files_modified": branch.files_modified,
+                            "work_item_identifiers": identifiers
+                        })
+                    )
+                    .await?;
+                progress.commits.insert(branch.sha.to_owned(), commit.id);
+            }
 
-        //创建引用
-        let _: ExtractId = self
-            .http_post(
-                format!("v1/scm/products/{}/repositories/{}/refs", prod_id, repo_id),
-                json::json!({
-                    "meta_type": "branch",
-                    "meta_id": branch_id,
-                    "sha": meta.sha
-                })
-            )
-            .await?;
+            //创建引用
+            if !progress.refs.contains(&branch.sha) {
+                let _: ExtractId = self
+                    .http_post(
+                        format!("v1/scm/products/{}/repositories/{}/refs", prod_id, repo_id),
+                        json::json!({
+                            "meta_type": meta_type,
+                            "meta_id": branch_id,
+                            "sha": branch.sha
+                        })
+                    )
+                    .await?;
+                progress.refs.push(branch.sha.to_owned());
+            }
+        }
 
-        //完成工作项状态
-        for id in commit_ids.0 {
-            self.finish_work_item(id).await?;
+        //在工作项上添加评论(只创建引用时不添加)
+        if self.comment.enabled && meta.branches.iter().any(|branch| !branch.ref_only) {
+            for id in &identifiers {
+                if progress.commented.contains(id) {
+                    continue;
+                }
+                let comment: Vec<&str> = commands
+                    .commands
+                    .iter()
+                    .filter_map(|command| {
+                        match command {
+                            smart_commit::Command::Comment {
+                                id: comment_id,
+                                text
+                            } if comment_id == id => Some(text.as_str()),
+                            _ => None
+                        }
+                    })
+                    .collect();
+                if let Some(work_item) = self.referenced_work_item(id, progress).await? {
+                    self.comment_work_item(
+                        &work_item,
+                        &self.comment.render(&meta, &committer.display_name, &comment)
+                    )
+                    .await?;
+                    progress.commented.push(id.to_owned());
+                }
+            }
+        }
+
+        //登记工时(只创建引用时不登记)
+        if self.worklog.enabled && meta.branches.iter().any(|branch| !branch.ref_only) {
+            match (self.worklog.collect(&commands), &committer.member) {
+                (Ok(worklogs), Some(member)) => {
+                    for worklog in worklogs {
+                        if progress.logged.contains(&worklog.id) {
+                            continue;
+                        }
+                        if let Some(work_item) = self.referenced_work_item(&worklog.id, progress).await? {
+                            self.log_work_item(&work_item, &worklog, member, &meta).await?;
+                            progress.logged.push(worklog.id);
+                        }
+                    }
+                },
+                //超过上限时不登记，重试也不会成功
+                (Err(e), _) => warn!("skip worklogs of {}: {}", meta.revision, e),
+                //工时必须登记到成员
+                (Ok(worklogs), None) => {
+                    if !worklogs.is_empty() {
+                        warn!(
+                            "skip worklogs of {}: author {} is not mapped",
+                            meta.revision, meta.committer_name
+                        )
+                    }
+                },
+            }
+        }
+
+        //修改工作项状态(未配置的关键字仅关联)
+        for (keyword, id) in commands.transitions() {
+            if progress.finished.iter().any(|finished| finished == id) || !self.workflow.has_keyword(keyword)
+            {
+                continue;
+            }
+            if let Some(work_item) = self.referenced_work_item(id, progress).await? {
+                self.transition_work_item(keyword, id, &work_item).await?;
+                progress.finished.push(id.to_owned());
+            }
         }
 
         Ok(())
@@ -221,17 +727,22 @@ impl Client {
 
     /// 获取代码托管平台ID
     async fn product_id(&self) -> Result<String, AnyError> {
-        if let Some(id) = &self.ctx.read().unwrap().product_id {
-            return Ok(id.to_owned());
+        let key = cache::Key::Product {
+            name: self.product_name.to_owned()
+        };
+        if let Some(id) = self.ctx.read().unwrap().id(&key) {
+            return Ok(id);
         }
 
         //查询
-        let products: ExtractIds =
-            self.http_get(format!("v1/scm/products?name={}", self.product_name)).await?;
-        if !products.values.is_empty() {
-            let id = products.values[0].id.to_owned();
-            self.ctx.write().unwrap().product_id = Some(id.to_owned());
-            return Ok(id);
+        if let Some(product) = self
+            .find_by_name("v1/scm/products", &[("name", &self.product_name)], |product| {
+                product.name_is(&self.product_name)
+            })
+            .await?
+        {
+            self.ctx.write().unwrap().put_id(key, &product.id);
+            return Ok(product.id);
         }
 
         //创建
@@ -246,26 +757,142 @@ impl Client {
             )
             .await?;
 
-        self.ctx.write().unwrap().product_id = Some(product.id.to_owned());
+        self.ctx.write().unwrap().put_id(key, &product.id);
 
         Ok(product.id)
     }
 
+    /// 获取提交者对应的代码托管平台用户，未找到成员时记录到未映射列表
+    async fn committer(&self, author: &str, revision: &str, sha: &str) -> Result<Committer, AnyError> {
+        match self.member(author).await? {
+            Some(member) => {
+                Ok(Committer {
+                    name: member.name.to_owned(),
+                    display_name: member.display_name.to_owned(),
+                    member: Some(member)
+                })
+            },
+            None => {
+                let mut ctx = self.ctx.write().unwrap();
+                let new_commit =
+                    ctx.unmapped_commits.entry(author.to_owned()).or_default().insert(sha.to_owned());
+                let unmapped = ctx.unmapped.entry(author.to_owned()).or_insert_with(|| {
+                    members::UnmappedAuthor {
+                        name: author.to_owned(),
+                        commits: 0,
+                        last_revision: String::new(),
+                        last_seen: chrono::Local::now().naive_local()
+                    }
+                });
+                if new_commit {
+                    unmapped.commits += 1;
+                }
+                unmapped.last_revision = revision.to_owned();
+                unmapped.last_seen = chrono::Local::now().naive_local();
+                Ok(Committer {
+                    name: author.to_owned(),
+                    display_name: author.to_owned(),
+                    member: None
+                })
+            }
+        }
+    }
+
+    /// 缓存的ID列表和访问令牌的过期时间
+    pub fn cached_ids(&self) -> (Vec<(String, String)>, Option<chrono::NaiveDateTime>) {
+        let ctx = self.ctx.read().unwrap();
+        let mut ids: Vec<(&cache::Key, &String)> = ctx.ids.iter().collect();
+        ids.sort();
+        (
+            ids.into_iter().map(|(key, id)| (key.to_string(), id.to_owned())).collect(),
+            self.tokens.current().map(|token| token.expires_in)
+        )
+    }
+
+    /// 清空缓存的ID、访问令牌和提交者对应的成员
+    pub fn clear_cache(&self) -> Result<(), AnyError> {
+        let mut ctx = self.ctx.write().unwrap();
+        if let Some(cache) = &mut ctx.cache {
+            cache.clear()?;
+        }
+        ctx.ids.clear();
+        ctx.members.clear();
+        self.tokens.clear();
+        Ok(())
+    }
+
+    /// 未找到对应成员的提交者列表
+    pub fn unmapped_authors(&self) -> Vec<members::UnmappedAuthor> {
+        self.ctx.read().unwrap().unmapped.values().cloned().collect()
+    }
+
+    /// 查找提交者对应的成员
+    /// 找到的成员一直缓存，未找到时间隔一段时间后重新查找
+    pub async fn member(&self, author: &str) -> Result<Option<Member>, AnyError> {
+        match self.ctx.read().unwrap().members.get(author) {
+            Some((Some(member), _)) => return Ok(Some(member.clone())),
+            Some((None, looked_up)) if looked_up.elapsed() < Duration::from_secs(UNMAPPED_RETRY_SECS) => {
+                return Ok(None)
+            },
+            _ => {}
+        }
+
+        let mut found = None;
+        for candidate in self.members.candidates(author) {
+            found = self.find_member(&candidate).await?;
+            if found.is_some() {
+                break;
+            }
+        }
+        let mut ctx = self.ctx.write().unwrap();
+        if found.is_none() {
+            warn!("author {} is not mapped to any worktile member", author);
+        } else {
+            ctx.unmapped.remove(author);
+            ctx.unmapped_commits.remove(author);
+        }
+        ctx.members.insert(author.to_owned(), (found.clone(), Instant::now()));
+
+        Ok(found)
+    }
+
+    /// 按邮箱、用户名或显示名查找成员
+    async fn find_member(&self, keyword: &str) -> Result<Option<Member>, AnyError> {
+        //关键字为模糊匹配，只取完全相同的成员
+        let matches = |member: &Member| {
+            if keyword.contains('@') {
+                member.email.eq_ignore_ascii_case(keyword)
+            } else {
+                member.name == keyword || member.display_name == keyword
+            }
+        };
+        let members: Vec<Member> =
+            self.http_get_list("v1/directory/users", &[("keywords", keyword)], matches).await?;
+
+        Ok(members.into_iter().find(matches))
+    }
+
     /// 获取代码托管平台的用户ID列表
-    async fn user_id(&self, name: impl AsRef<str>) -> Result<String, AnyError> {
-        if let Some(id) = self.ctx.read().unwrap().users.get(name.as_ref()) {
-            return Ok(id.to_owned());
+    async fn user_id(&self, committer: &Committer) -> Result<String, AnyError> {
+        let name = committer.name.as_str();
+        let key = cache::Key::User {
+            name: name.to_owned()
+        };
+        if let Some(id) = self.ctx.read().unwrap().id(&key) {
+            return Ok(id);
         }
 
         let prod_id = self.product_id().await?;
 
         //查询
-        let users: ExtractIds =
-            self.http_get(format!("v1/scm/products/{}/users?name={}", prod_id, name.as_ref())).await?;
-        if !users.values.is_empty() {
-            let id = users.values[0].id.to_owned();
-            self.ctx.write().unwrap().users.insert(name.as_ref().to_owned(), id.to_owned());
-            return Ok(id);
+        if let Some(user) = self
+            .find_by_name(format!("v1/scm/products/{}/users", prod_id), &[("name", name)], |user| {
+                user.name_is(name)
+            })
+            .await?
+        {
+            self.ctx.write().unwrap().put_id(key, &user.id);
+            return Ok(user.id);
         }
 
         //创建
@@ -273,33 +900,44 @@ impl Client {
             .http_post(
                 format!("v1/scm/products/{}/users", prod_id),
                 json::json!({
-                    "name": name.as_ref(),
-                    "display_name": name.as_ref(),
+                    "name": name,
+                    "display_name": committer.display_name,
                 })
             )
             .await?;
 
-        self.ctx.write().unwrap().users.insert(name.as_ref().to_owned(), user.id.to_owned());
+        self.ctx.write().unwrap().put_id(key, &user.id);
 
         Ok(user.id)
     }
 
     /// 获取代码仓库ID
     async fn repository_id(&self, name: impl AsRef<str>) -> Result<String, AnyError> {
-        if let Some(id) = self.ctx.read().unwrap().repositories.get(name.as_ref()) {
-            return Ok(id.to_owned());
+        let key = cache::Key::Repository {
+            name: name.as_ref().to_owned()
+        };
+        if let Some(id) = self.ctx.read().unwrap().id(&key) {
+            return Ok(id);
         }
 
         let prod_id = self.product_id().await?;
 
         //查询
-        let repos: ExtractIds = self
-            .http_get(format!("v1/scm/products/{}/repositories?full_name={}", prod_id, name.as_ref()))
-            .await?;
-        if !repos.values.is_empty() {
-            let id = repos.values[0].id.to_owned();
-            self.ctx.write().unwrap().repositories.insert(name.as_ref().to_owned(), id.to_owned());
-            return Ok(id);
+        if let Some(repo) = self
+            .find_by_name(
+                format!("v1/scm/products/{}/repositories", prod_id),
+                &[("full_name", name.as_ref())],
+                |repo| {
+                    match &repo.full_name {
+                        Some(full_name) => full_name == name.as_ref(),
+                        None => repo.name_is(name.as_ref())
+                    }
+                }
+            )
+            .await?
+        {
+            self.ctx.write().unwrap().put_id(key, &repo.id);
+            return Ok(repo.id);
         }
 
         //创建
@@ -317,34 +955,35 @@ impl Client {
             )
             .await?;
 
-        self.ctx.write().unwrap().repositories.insert(name.as_ref().to_owned(), repo.id.to_owned());
+        self.ctx.write().unwrap().put_id(key, &repo.id);
 
         Ok(repo.id)
     }
 
     /// 获取代码仓库的分支ID列表
     async fn branch_id(&self, repo: impl AsRef<str>, name: impl AsRef<str>) -> Result<String, AnyError> {
-        let key = (repo.as_ref().to_owned(), name.as_ref().to_owned());
-        if let Some(id) = self.ctx.read().unwrap().branches.get(&key) {
-            return Ok(id.to_owned());
+        let key = cache::Key::Branch {
+            repo: repo.as_ref().to_owned(),
+            name: name.as_ref().to_owned()
+        };
+        if let Some(id) = self.ctx.read().unwrap().id(&key) {
+            return Ok(id);
         }
 
         let prod_id = self.product_id().await?;
         let repo_id = self.repository_id(repo.as_ref()).await?;
 
         //查询
-        let branches: ExtractIds = self
-            .http_get(format!(
-                "v1/scm/products/{}/repositories/{}/branches?name={}",
-                prod_id,
-                repo_id,
-                name.as_ref()
-            ))
-            .await?;
-        if !branches.values.is_empty() {
-            let id = branches.values[0].id.to_owned();
-            self.ctx.write().unwrap().branches.insert(key, id.to_owned());
-            return Ok(id);
+        if let Some(branch) = self
+            .find_by_name(
+                format!("v1/scm/products/{}/repositories/{}/branches", prod_id, repo_id),
+                &[("name", name.as_ref())],
+                |branch| branch.name_is(name.as_ref())
+            )
+            .await?
+        {
+            self.ctx.write().unwrap().put_id(key, &branch.id);
+            return Ok(branch.id);
         }
 
         //创建
@@ -359,22 +998,80 @@ impl Client {
             )
             .await?;
 
-        self.ctx.write().unwrap().branches.insert(key, branch.id.to_owned());
+        self.ctx.write().unwrap().put_id(key, &branch.id);
 
         Ok(branch.id)
     }
 
-    /// 完成用户故事状态
-    async fn finish_work_item(&self, identifier: impl AsRef<str>) -> Result<(), AnyError> {
-        let work_item = self.work_item(identifier).await?;
-        let state_id = self.state_id("已完成").await?;
+    /// 获取代码仓库的标签ID列表
+    async fn tag_id(&self, repo: impl AsRef<str>, name: impl AsRef<str>) -> Result<String, AnyError> {
+        let key = cache::Key::Tag {
+            repo: repo.as_ref().to_owned(),
+            name: name.as_ref().to_owned()
+        };
+        if let Some(id) = self.ctx.read().unwrap().id(&key) {
+            return Ok(id);
+        }
 
-        match work_item.state.name.as_str() {
-            "新建" | "进行中" => {},
-            //排除其他状态
-            _ => return Ok(())
+        let prod_id = self.product_id().await?;
+        let repo_id = self.repository_id(repo.as_ref()).await?;
+
+        //查询
+        if let Some(tag) = self
+            .find_by_name(
+                format!("v1/scm/products/{}/repositories/{}/tags", prod_id, repo_id),
+                &[("name", name.as_ref())],
+                |tag| tag.name_is(name.as_ref())
+            )
+            .await?
+        {
+            self.ctx.write().unwrap().put_id(key, &tag.id);
+            return Ok(tag.id);
         }
 
+        //创建
+        let tag: ExtractId = self
+            .http_post(
+                format!("v1/scm/products/{}/repositories/{}/tags", prod_id, repo_id),
+                json::json!({
+                    "name": name.as_ref(),
+                    "sender_name": "admin",
+                    "created_at": chrono::Utc::now().timestamp(),
+                })
+            )
+            .await?;
+
+        self.ctx.write().unwrap().put_id(key, &tag.id);
+
+        Ok(tag.id)
+    }
+
+    /// 按状态流转规则修改工作项状态
+    /// 没有匹配的规则或当前状态不允许流转时不修改
+    async fn transition_work_item(
+        &self,
+        keyword: &str,
+        identifier: impl AsRef<str>,
+        work_item: &WorkItem
+    ) -> Result<(), AnyError> {
+        let transition = match self.workflow.transition(keyword, &work_item.item_type) {
+            Some(transition) => transition,
+            None => {
+                info!(
+                    "work item {} ({}) has no transition for keyword [{}]",
+                    identifier.as_ref(),
+                    work_item.item_type,
+                    keyword
+                );
+                return Ok(());
+            }
+        };
+        //排除其他状态
+        if work_item.state.name == transition.to || !transition.allows(&work_item.state.name) {
+            return Ok(());
+        }
+        let state_id = self.state_id(&transition.to).await?;
+
         //根据工作项类型取存储分类
         let cat = match work_item.item_type.as_str() {
             "epic" => "epics",
@@ -394,93 +1091,209 @@ impl Client {
         Ok(())
     }
 
-    /// 查询指定工作项
-    async fn work_item(&self, identifier: impl AsRef<str>) -> Result<WorkItem, AnyError> {
-        #[derive(Deserialize)]
-        struct WorkItemsResult {
-            values: Vec<WorkItem>
+    /// 查询提交日志中提及的工作项
+    /// 不存在时(如编号写错)记录到同步进度并跳过，不影响提交的其它步骤，重试时也不再查询
+    async fn referenced_work_item(
+        &self,
+        identifier: &str,
+        progress: &mut CommitProgress
+    ) -> Result<Option<WorkItem>, AnyError> {
+        if progress.missing.iter().any(|missing| missing == identifier) {
+            return Ok(None);
+        }
+        let work_item = self.work_item(identifier).await?;
+        if work_item.is_none() {
+            warn!("work item {} not found, skipped", identifier);
+            progress.missing.push(identifier.to_owned());
         }
+        Ok(work_item)
+    }
+
+    /// 在工作项上添加评论
+    async fn comment_work_item(&self, work_item: &WorkItem, content: &str) -> Result<(), AnyError> {
+        let _: ExtractId = self
+            .http_post(
+                format!("v1/agile/work_items/{}/comments", work_item.id),
+                json::json!({ "content": content })
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// 在工作项上登记工时
+    async fn log_work_item(
+        &self,
+        work_item: &WorkItem,
+        worklog: &worklog::Worklog,
+        member: &Member,
+        meta: &CommitMeta
+    ) -> Result<(), AnyError> {
+        let description = if worklog.comment.is_empty() {
+            format!("{} {}", meta.revision, meta.message.lines().next().unwrap_or_default())
+        } else {
+            format!("{} {}", meta.revision, worklog.comment)
+        };
+        let _: ExtractId = self
+            .http_post(
+                format!("v1/agile/work_items/{}/workloads", work_item.id),
+                json::json!({
+                    "duration": worklog.minutes as f64 / 60.0,
+                    "description": description.trim(),
+                    "reported_at": meta.committed_at.timestamp(),
+                    "reported_by": member.uid
+                })
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// 查询指定工作项，不存在时返回`None`
+    pub async fn work_item(&self, identifier: impl AsRef<str>) -> Result<Option<WorkItem>, AnyError> {
+        //编号必须完全相同(不区分大小写)
+        let matches = |item: &WorkItem| {
+            !item.identifier.is_empty() && item.identifier.eq_ignore_ascii_case(identifier.as_ref())
+        };
         //查询
-        let mut result: WorkItemsResult =
-            self.http_get(format!("v1/agile/work_items?identifier={}", identifier.as_ref())).await?;
+        let items: Vec<WorkItem> = self
+            .http_get_list("v1/agile/work_items", &[("identifier", identifier.as_ref())], matches)
+            .await?;
 
-        result.values.pop().ok_or(format!("工作项[{}]不存在", identifier.as_ref()).into())
+        Ok(items.into_iter().find(matches))
     }
 
     /// 获取状态Id
     async fn state_id(&self, name: impl AsRef<str>) -> Result<String, AnyError> {
-        let mut state_id = {
-            let ctx = self.ctx.read().unwrap();
-            if let Some(id) = ctx.states.get(name.as_ref()) {
-                Some(id.to_owned())
-            } else {
-                if !ctx.states.is_empty() {
-                    return Err(format!("[{}]状态Id不存在", name.as_ref()).into());
-                }
-                None
-            }
+        let key = cache::Key::State {
+            name: name.as_ref().to_owned()
         };
-        if state_id.is_none() {
-            #[derive(Deserialize)]
-            struct State {
-                id: String,
-                name: String
-            }
-            #[derive(Deserialize)]
-            struct StatesResult {
-                values: Vec<State>
-            }
-            //查询
-            let states: StatesResult = self.http_get("v1/agile/states").await?;
-            let mut ctx = self.ctx.write().unwrap();
-            for state in states.values {
-                ctx.states.insert(state.name, state.id);
-            }
-            if let Some(id) = ctx.states.get(name.as_ref()) {
-                state_id = Some(id.to_owned())
-            } else {
-                return Err(format!("[{}]状态Id不存在", name.as_ref()).into());
+        {
+            let ctx = self.ctx.read().unwrap();
+            if let Some(id) = ctx.id(&key) {
+                return Ok(id);
             }
         }
 
-        Ok(state_id.unwrap())
-    }
-
-    /// 获取访问令牌
-    async fn access_token(&self) -> Result<String, AnyError> {
-        if let Some(access_token) = &self.ctx.read().unwrap().access_token {
-            return Ok(access_token.token.to_owned());
+        #[derive(Deserialize)]
+        struct State {
+            id: String,
+            name: String
         }
-
+        //查询全部状态
+        let states: Vec<State> = self.http_get_list("v1/agile/states", &[], |_| false).await?;
         let mut ctx = self.ctx.write().unwrap();
+        for state in states {
+            let state_key = cache::Key::State {
+                name: state.name
+            };
+            if ctx.id(&state_key).as_ref() != Some(&state.id) {
+                ctx.put_id(state_key, &state.id);
+            }
+        }
+        ctx.id(&key).ok_or_else(|| format!("[{}]状态Id不存在", name.as_ref()).into())
+    }
 
-        //防止并发时重复刷新令牌
-        if let Some(access_token) = &ctx.access_token {
-            if access_token.expires_in.signed_duration_since(chrono::Utc::now().naive_utc()).num_hours() >
-                TOKEN_REFRESH_THRESOLD
-            {
-                return Ok(access_token.token.to_owned());
+    /// 获取访问令牌
+    async fn access_token(&self) -> Result<String, AnyError> { self.tokens.get(|| self.fetch_token()).await }
+
+    /// 在令牌过期前提前刷新(后台任务)
+    pub async fn refresh_token_loop(self) {
+        let min_delay = std::time::Duration::from_secs(TOKEN_REFRESH_INTERVAL_SECS);
+        loop {
+            let current = self.tokens.current();
+            //没有令牌时立即获取，否则等到刷新时间(避免有效期较短时频繁刷新)
+            if let Some(token) = &current {
+                let delay =
+                    (token.refresh_at() - chrono::Utc::now().naive_utc()).to_std().unwrap_or_default();
+                tokio::time::delay_for(std::cmp::max(delay, min_delay)).await;
+            }
+            let seen = current.as_ref().map(|token| token.token.as_str());
+            if let Err(e) = self.tokens.refresh(seen, || self.fetch_token()).await {
+                warn!("refresh access token failed: {}", e);
+                tokio::time::delay_for(min_delay).await;
             }
         }
+    }
 
-        let uri = format!(
-            "v1/auth/token?grant_type=client_credentials&client_id={}&client_secret={}",
-            self.id, self.key
-        );
+    /// 请求新的访问令牌
+    async fn fetch_token(&self) -> Result<token::Token, AnyError> {
+        let query =
+            [("grant_type", "client_credentials"), ("client_id", &self.id), ("client_secret", &self.key)];
+        let access_token: AccessToken =
+            match self.http_request_impl(reqwest::Method::GET, "v1/auth/token", &query, None, None).await {
+                Ok(resp) => decode("GET v1/auth/token", resp)?,
+                //认证信息错误
+                Err(e) if e.category() == ErrorCategory::Auth => {
+                    return Err(ApiError::Auth {
+                        request: e.request().to_owned(),
+                        message: e.to_string()
+                    }
+                    .into())
+                },
+                Err(e) => return Err(e.into())
+            };
+        let token = token::Token {
+            token: access_token.token,
+            expires_in: access_token.expires_in,
+            issued_at: Some(chrono::Utc::now().naive_utc())
+        };
+        self.ctx.write().unwrap().put_token(&token);
+        Ok(token)
+    }
 
-        ctx.access_token = None;
-        ctx.access_token =
-            Some(json::from_value(self.http_request_impl(reqwest::Method::GET, &uri, None, None).await?)?);
+    /// 发起HTTP GET请求，`query`为查询参数(名称, 值)
+    async fn http_get<R>(&self, uri: impl AsRef<str>, query: &[(&str, &str)]) -> Result<R, AnyError>
+    where
+        R: DeserializeOwned
+    {
+        self.http_request(reqwest::Method::GET, uri, query, None).await
+    }
 
-        Ok(ctx.access_token.as_ref().unwrap().token.to_owned())
+    /// 按名称查询对象，名称必须完全相同
+    async fn find_by_name(
+        &self,
+        uri: impl AsRef<str>,
+        query: &[(&str, &str)],
+        matches: impl Fn(&NamedId) -> bool
+    ) -> Result<Option<NamedId>, AnyError> {
+        let values = self.http_get_list(uri, query, &matches).await?;
+        Ok(values.into_iter().find(|value| matches(value)))
     }
 
-    /// 发起HTTP GET请求
-    async fn http_get<R>(&self, uri: impl AsRef<str>) -> Result<R, AnyError>
+    /// 分页查询列表，`found`返回`true`时不再查询后续分页
+    async fn http_get_list<T>(
+        &self,
+        uri: impl AsRef<str>,
+        query: &[(&str, &str)],
+        found: impl Fn(&T) -> bool
+    ) -> Result<Vec<T>, AnyError>
     where
-        R: DeserializeOwned
+        T: DeserializeOwned
     {
-        self.http_request(reqwest::Method::GET, uri, None).await
+        let page_size = PAGE_SIZE.to_string();
+        let mut values = Vec::new();
+        for page_index in 0..MAX_PAGES {
+            let page_index_param = page_index.to_string();
+            let mut params = query.to_vec();
+            params.push(("page_index", &page_index_param));
+            params.push(("page_size", &page_size));
+            let page: Page<T> = self.http_get(uri.as_ref(), &params).await?;
+            let count = page.values.len() as u64;
+            let done = page.values.iter().any(|value| found(value));
+            values.extend(page.values);
+            //接口未返回分页信息时按返回数量判断
+            let page_size = if page.page_size > 0 {
+                page.page_size
+            } else {
+                PAGE_SIZE
+            };
+            if done || count < page_size || (page.total > 0 && (page_index + 1) * page_size >= page.total) {
+                return Ok(values);
+            }
+        }
+        warn!("list {} exceeds {} pages", uri.as_ref(), MAX_PAGES);
+        Ok(values)
     }
 
     /// 发起HTTP POST请求
@@ -488,7 +1301,7 @@ impl Client {
     where
         R: DeserializeOwned
     {
-        self.http_request(reqwest::Method::POST, uri, body).await
+        self.http_request(reqwest::Method::POST, uri, &[], body).await
     }
 
     /// 发起HTTP PATCH请求
@@ -496,14 +1309,15 @@ impl Client {
     where
         R: DeserializeOwned
     {
-        self.http_request(reqwest::Method::PATCH, uri, body).await
+        self.http_request(reqwest::Method::PATCH, uri, &[], body).await
     }
 
-    /// 发起HTTP请求
+    /// 发起HTTP请求，查询参数由`query`指定(自动编码)
     async fn http_request<R>(
         &self,
         method: reqwest::Method,
         uri: impl AsRef<str>,
+        query: &[(&str, &str)],
         body: impl Into<Option<json::Value>>
     ) -> Result<R, AnyError>
     where
@@ -515,45 +1329,53 @@ impl Client {
             let access_token = self.access_token().await?;
             let mut headers = reqwest::header::HeaderMap::new();
             headers.append("authorization", format!("Bearer {}", access_token).parse()?);
-            let resp =
-                self.http_request_impl(method.clone(), uri.as_ref(), body.as_ref(), Some(headers)).await?;
-            // 检查响应结果判断是否需要刷新令牌
-            if let Some(code) =
-                resp.as_object().and_then(|obj| obj.get("code")).and_then(|code| code.as_str())
+            match self
+                .http_request_impl(method.clone(), uri.as_ref(), query, body.as_ref(), Some(headers))
+                .await
             {
-                //100026 	'access_token'无效
-                //100028 	'access_token'已失效
-                //100032 	'authorization_code'鉴权失败
-                if (code == "100028" || code == "100032") && !tried {
-                    let mut ctx = self.ctx.write().unwrap();
-                    if let Some(token) = &ctx.access_token {
-                        //检查令牌是否发生了改变，防止并发
-                        if token.token == access_token {
-                            //清空当前的令牌，使强制刷新
-                            ctx.access_token = None;
+                Ok(resp) => break resp,
+                //令牌无效时作废当前的令牌，重新获取后重试一次
+                Err(e) if e.category() == ErrorCategory::Auth => {
+                    self.tokens.invalidate(&access_token);
+                    if tried {
+                        return Err(ApiError::Auth {
+                            request: e.request().to_owned(),
+                            message: e.to_string()
                         }
+                        .into());
                     }
                     tried = true;
-                    continue;
-                } else {
-                    return Err(format!("Worktile API error code: {}", code).into());
-                }
+                },
+                //请求中使用的缓存ID可能已被删除
+                Err(e) if e.category() == ErrorCategory::NotFound => {
+                    if self.ctx.write().unwrap().invalidate(uri.as_ref()) {
+                        return Err(ApiError::Stale {
+                            request: e.request().to_owned()
+                        }
+                        .into());
+                    }
+                    return Err(e.into());
+                },
+                Err(e) => return Err(e.into())
             }
-            break resp;
         };
-        json::from_value(resp).map_err(|e| e.into())
+        Ok(decode(&format!("{} {}", method, uri.as_ref()), resp)?)
     }
 
     /// 发起HTTP请求
+    /// 返回错误代码或HTTP状态码不为2xx时返回错误
     async fn http_request_impl(
         &self,
         method: reqwest::Method,
         uri: &str,
+        query: &[(&str, &str)],
         body: Option<&json::Value>,
         headers: Option<reqwest::header::HeaderMap>
-    ) -> Result<json::Value, AnyError> {
+    ) -> Result<json::Value, ApiError> {
         let url = format!("{}/{}", self.api_url, uri);
-        let mut req = self.client.request(method.clone(), &url);
+        //错误信息和日志中不包含查询参数，避免泄露认证信息
+        let request = format!("{} {}", method, uri);
+        let mut req = self.client.request(method.clone(), &url).query(query);
         req = match headers {
             Some(headers) => req.headers(headers),
             None => req
@@ -568,15 +1390,62 @@ impl Client {
             url,
             body.map(|v| v.to_string()).unwrap_or("NULL".to_owned())
         );
-        let resp = req.send().await?;
-        if resp.status().is_success() {
+        let resp = req.send().await.map_err(|source| {
+            ApiError::Transport {
+                request: request.to_owned(),
+                source
+            }
+        })?;
+        let status = resp.status();
+        if status.is_success() {
             info!("{:?}", resp);
         } else {
             warn!("{:?}", resp);
         }
-        let resp: json::Value = resp.json().await?;
-        info!("Response JSON, Url: {}, Body: {}", url, resp);
-        Ok(resp)
+        let text = resp.text().await.map_err(|source| {
+            ApiError::Transport {
+                request: request.to_owned(),
+                source
+            }
+        })?;
+        info!("Response, Url: {}, Body: {}", url, text);
+
+        let resp: Option<json::Value> = json::from_str(&text).ok();
+        //检查接口返回的错误代码
+        if let Some(obj) = resp.as_ref().and_then(|resp| resp.as_object()) {
+            let code = obj.get("code").and_then(|code| {
+                code.as_str()
+                    .map(|code| code.to_owned())
+                    .or_else(|| code.as_i64().map(|code| code.to_string()))
+            });
+            if let Some(code) = code {
+                let message = ["message", "msg"]
+                    .iter()
+                    .filter_map(|key| obj.get(*key).and_then(|msg| msg.as_str()))
+                    .next()
+                    .unwrap_or_default();
+                return Err(ApiError::Code {
+                    request,
+                    status: status.as_u16(),
+                    category: code_category(&code, &self.error_codes),
+                    code,
+                    message: message.to_owned()
+                });
+            }
+        }
+        if !status.is_success() {
+            return Err(ApiError::Status {
+                request,
+                status: status.as_u16(),
+                message: text.chars().take(200).collect()
+            });
+        }
+        resp.ok_or_else(|| {
+            ApiError::Decode {
+                request,
+                message: format!("invalid json: {}", text.chars().take(200).collect::<String>())
+            }
+        })
     }
 }
 
@@ -590,23 +1459,14 @@ fn tree_id(repo: &str, branch: &str) -> Result<String, AnyError> {
     Ok(hex::encode(hasher.finish()?))
 }
 
-/// 从提交的Message里提取关联的Worktile工作项编号
-/// 如: #PROD-1234 (完成), @PROD-1234 (关联)
-/// 返回: tuple ([完成列表],[关联列表])
-fn identifiers_from_message(message: &str) -> Result<(Vec<String>, Vec<String>), AnyError> {
-    use regex::Regex;
-
-    let re = Regex::new(r"(?m)[#@][^\s]*[A-Za-z0-9_]+-[0-9]+")?;
-    let mut finished = Vec::new();
-    let mut related = Vec::new();
-    for item in re.find_iter(message) {
-        if item.as_str().starts_with("#") {
-            finished.push(item.as_str()[1..].to_owned());
-        } else {
-            related.push(item.as_str()[1..].to_owned());
+/// 解析响应数据
+fn decode<R: DeserializeOwned>(request: &str, resp: json::Value) -> Result<R, ApiError> {
+    json::from_value(resp).map_err(|e| {
+        ApiError::Decode {
+            request: request.to_owned(),
+            message: e.to_string()
         }
-    }
-    Ok((finished, related))
+    })
 }
 
 /// 解析Timestamp数值(秒)
@@ -617,3 +1477,342 @@ where
     let ts = i64::deserialize(deserializer)?;
     Ok(chrono::NaiveDateTime::from_timestamp(ts, 0))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_worktile::{Fault, MockWorktile};
+    use std::time::Duration;
+
+    fn workflow() -> workflow::Workflow {
+        workflow::Workflow {
+            default_keyword: "done".to_owned(),
+            transitions: vec![workflow::Transition {
+                keywords: vec!["done".to_owned()],
+                types: Vec::new(),
+                from: vec!["新建".to_owned()],
+                to: "已完成".to_owned()
+            }]
+        }
+    }
+
+    fn meta(repo: &str, sha: &str, message: &str) -> CommitMeta {
+        CommitMeta {
+            revision: "r1".to_owned(),
+            message: message.to_owned(),
+            committer_name: "zhangsan".to_owned(),
+            committed_at: chrono::NaiveDateTime::from_timestamp(1_590_000_000, 0),
+            branches: vec![CommitBranch {
+                repo: repo.to_owned(),
+                name: "trunk".to_owned(),
+                tag: false,
+                sha: sha.to_owned(),
+                ref_only: false,
+                files_added: vec!["/trunk/a.txt".to_owned()],
+                files_removed: Vec::new(),
+                files_modified: Vec::new()
+            }]
+        }
+    }
+
+    #[actix_rt::test]
+    async fn commit_creates_scm_objects() {
+        let wt = MockWorktile::start();
+        wt.add_work_item("CD-7", "task", "新建");
+        wt.add_state("已完成");
+        let client = wt.client().workflow(workflow()).build().unwrap();
+
+        let mut progress = CommitProgress::default();
+        client.commit(meta("repo", "sha1", "#CD-7 fix"), &mut progress).await.unwrap();
+
+        assert_eq!(wt.objects("products").len(), 1);
+        assert_eq!(wt.objects("repositories").len(), 1);
+        assert_eq!(wt.objects("branches").len(), 1);
+        let commits = wt.objects("commits");
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].body["work_item_identifiers"], json::json!(["CD-7"]));
+        assert_eq!(wt.objects("refs").len(), 1);
+        let item = wt.work_item("CD-7").unwrap();
+        assert_eq!(item.state, "已完成");
+        assert_eq!(item.comments.len(), 1);
+        assert_eq!(progress.finished, vec!["CD-7".to_owned()]);
+
+        //已缓存的对象不再查询或创建
+        wt.clear_calls();
+        client.commit(meta("repo", "sha2", "@CD-7"), &mut CommitProgress::default()).await.unwrap();
+        assert!(wt.calls_to("GET", "v1/scm/products").is_empty());
+        assert!(wt.calls_to("POST", "v1/scm/products/").iter().all(|call| call.path.ends_with("/refs")));
+        assert_eq!(wt.objects("commits").len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn missing_work_item_is_skipped() {
+        let wt = MockWorktile::start();
+        wt.add_work_item("CD-7", "task", "新建");
+        wt.add_state("已完成");
+        let client = wt.client().workflow(workflow()).build().unwrap();
+
+        let mut progress = CommitProgress::default();
+        client.commit(meta("repo", "sha1", "#CD-7 @CD-99 #CD-98"), &mut progress).await.unwrap();
+
+        assert_eq!(wt.objects("commits").len(), 1);
+        assert_eq!(progress.commented, vec!["CD-7".to_owned()]);
+        assert_eq!(progress.finished, vec!["CD-7".to_owned()]);
+        assert_eq!(progress.missing, vec!["CD-99".to_owned(), "CD-98".to_owned()]);
+        assert_eq!(wt.work_item("CD-7").unwrap().state, "已完成");
+    }
+
+    #[test]
+    fn invalidate_matches_path_segments() {
+        let (mut ctx, _) = Context::open(None, "SVN").unwrap();
+        let product = cache::Key::Product {
+            name: "SVN".to_owned()
+        };
+        let repo = |name: &str| {
+            cache::Key::Repository {
+                name: name.to_owned()
+            }
+        };
+        let branch = |repo: &str| {
+            cache::Key::Branch {
+                repo: repo.to_owned(),
+                name: "trunk".to_owned()
+            }
+        };
+        let state = cache::Key::State {
+            name: "已完成".to_owned()
+        };
+        ctx.put_id(product.clone(), "p1");
+        ctx.put_id(repo("a"), "r1");
+        ctx.put_id(branch("a"), "b1");
+        ctx.put_id(repo("b"), "r2");
+        ctx.put_id(branch("b"), "b2");
+        ctx.put_id(state.clone(), "s1");
+
+        //地址中没有缓存的ID、只有部分相同时不删除
+        assert!(!ctx.invalidate("v1/agile/tasks/w1"));
+        assert!(!ctx.invalidate("v1/scm/products/p1x/users"));
+        assert_eq!(ctx.ids.len(), 6);
+
+        //仓库不存在时同时删除仓库下的分支
+        assert!(ctx.invalidate("v1/scm/products/p1/repositories/r1/refs"));
+        assert!(ctx.id(&repo("a")).is_none() && ctx.id(&branch("a")).is_none());
+        assert!(ctx.id(&product).is_some() && ctx.id(&branch("b")).is_some());
+
+        //代码托管平台不存在时删除平台下的全部对象，保留工作项状态
+        assert!(ctx.invalidate("v1/scm/products/p1/users"));
+        assert_eq!(ctx.ids.keys().collect::<Vec<_>>(), vec![&state]);
+    }
+
+    #[actix_rt::test]
+    async fn query_parameters_are_encoded() {
+        let wt = MockWorktile::start();
+        let client = wt.client().build().unwrap();
+        let name = "feature/a#b&c+d e/中文";
+        let branch_meta = |sha: &str| {
+            let mut meta = meta("repo", sha, "message");
+            meta.branches[0].name = name.to_owned();
+            meta
+        };
+
+        client.commit(branch_meta("sha1"), &mut CommitProgress::default()).await.unwrap();
+        //清空缓存后按名称查询到已创建的分支
+        client.clear_cache().unwrap();
+        wt.clear_calls();
+        client.commit(branch_meta("sha2"), &mut CommitProgress::default()).await.unwrap();
+        let branches = wt.objects("branches");
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].name, name);
+        assert!(wt
+            .calls_to("GET", "v1/scm/products/")
+            .iter()
+            .any(|call| call.path.ends_with("/branches") && call.param("name") == name));
+    }
+
+    #[actix_rt::test]
+    async fn cache_is_scoped_to_product() {
+        let wt = MockWorktile::start();
+        let path = std::env::temp_dir().join(format!(
+            "svn_commit_wt-cache-{}-{}.log",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        let cache_path = path.to_string_lossy().into_owned();
+
+        let client = wt.client().cache_path(cache_path.clone()).build().unwrap();
+        client.commit(meta("repo", "sha1", "first"), &mut CommitProgress::default()).await.unwrap();
+        drop(client);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        //同一平台重新打开时使用缓存的ID
+        wt.clear_calls();
+        let client = wt.client().cache_path(cache_path.clone()).build().unwrap();
+        client.commit(meta("repo", "sha2", "second"), &mut CommitProgress::default()).await.unwrap();
+        assert!(wt.calls_to("GET", "v1/scm/products").is_empty());
+        drop(client);
+
+        //修改平台名称后清空缓存，重新查询
+        wt.clear_calls();
+        let client = wt.client().product_name("Other").cache_path(cache_path).build().unwrap();
+        client.commit(meta("repo", "sha3", "third"), &mut CommitProgress::default()).await.unwrap();
+        assert!(!wt.calls_to("GET", "v1/scm/products").is_empty());
+        assert_eq!(wt.objects("products").len(), 2);
+        drop(client);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[actix_rt::test]
+    async fn unmapped_authors_count_distinct_revisions() {
+        let wt = MockWorktile::start();
+        let client = wt.client().build().unwrap();
+
+        client.commit(meta("repo", "sha1", "first"), &mut CommitProgress::default()).await.unwrap();
+        client.commit(meta("repo", "sha1", "first"), &mut CommitProgress::default()).await.unwrap();
+        let unmapped = client.unmapped_authors();
+        assert_eq!(unmapped.len(), 1);
+        assert_eq!((unmapped[0].name.as_str(), unmapped[0].commits), ("zhangsan", 1));
+        //其它仓库的相同版本号单独计数
+        client.commit(meta("other", "sha2", "first"), &mut CommitProgress::default()).await.unwrap();
+        assert_eq!(client.unmapped_authors()[0].commits, 2);
+
+        //成员后来加入Worktile，清空缓存后重新查找
+        wt.add_member("zhangsan", "张三", "zhangsan@example.com");
+        assert!(client.member("zhangsan").await.unwrap().is_none());
+        client.clear_cache().unwrap();
+        assert_eq!(client.member("zhangsan").await.unwrap().unwrap().display_name, "张三");
+        assert!(client.unmapped_authors().is_empty());
+        assert!(client.ctx.read().unwrap().unmapped_commits.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn lookup_requires_exact_name() {
+        let wt = MockWorktile::start();
+        let product_id = wt.add_product("SVN");
+        wt.add_repository(&product_id, "repo-old");
+        let client = wt.client().build().unwrap();
+
+        client.commit(meta("repo", "sha1", "no work item"), &mut CommitProgress::default()).await.unwrap();
+
+        let repos = wt.objects("repositories");
+        assert_eq!(repos.len(), 2);
+        let commit_tree = wt.objects("commits")[0].body["tree_id"].clone();
+        assert_eq!(commit_tree, json::json!(tree_id(&repos[1].id, &wt.objects("branches")[0].id).unwrap()));
+    }
+
+    #[actix_rt::test]
+    async fn lookup_pages_through_results() {
+        let wt = MockWorktile::start();
+        for num in 10..200 {
+            wt.add_work_item(&format!("CD-{}", num), "task", "新建");
+        }
+        wt.add_work_item("CD-1", "bug", "新建");
+        let client = wt.client().build().unwrap();
+
+        let item = client.work_item("CD-1").await.unwrap().unwrap();
+        assert_eq!(item.item_type, "bug");
+        assert_eq!(wt.calls_to("GET", "v1/agile/work_items").len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn expired_token_is_refreshed_once() {
+        let wt = MockWorktile::start();
+        wt.add_work_item("CD-7", "task", "新建");
+        let client = wt.client().build().unwrap();
+
+        client.work_item("CD-7").await.unwrap();
+        wt.fault("GET", "v1/agile/work_items", Fault::ExpiredToken, 1);
+        assert!(client.work_item("CD-7").await.unwrap().is_some());
+        assert_eq!(wt.calls_to("GET", "v1/auth/token").len(), 2);
+
+        //重新获取令牌后仍然失效时返回认证错误
+        wt.fault("GET", "v1/agile/work_items", Fault::ExpiredToken, 2);
+        let e = client.work_item("CD-7").await.unwrap_err();
+        assert_eq!(e.downcast_ref::<ApiError>().unwrap().category(), ErrorCategory::Auth);
+    }
+
+    #[actix_rt::test]
+    async fn server_error_is_retryable() {
+        let wt = MockWorktile::start();
+        let client = wt.client().build().unwrap();
+
+        wt.fault("GET", "v1/agile/work_items", Fault::Status(502), 1);
+        let e = client.work_item("CD-7").await.unwrap_err();
+        assert_eq!(e.downcast_ref::<ApiError>().unwrap().category(), ErrorCategory::Server);
+        assert!(crate::retry::RetryPolicy::default().is_retryable(&e));
+        assert!(client.work_item("CD-7").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn known_codes_are_categorized() {
+        let configured = HashMap::new();
+        for code in &["100026", "100028", "100032"] {
+            assert_eq!(code_category(code, &configured), ErrorCategory::Auth);
+        }
+        assert_eq!(code_category("999999", &configured), ErrorCategory::Unknown);
+
+        //配置的分类优先于内置的分类
+        let configured: HashMap<String, ErrorCategory> =
+            vec![("100026".to_owned(), ErrorCategory::Forbidden)].into_iter().collect();
+        assert_eq!(code_category("100026", &configured), ErrorCategory::Forbidden);
+    }
+
+    #[actix_rt::test]
+    async fn configured_codes_are_categorized() {
+        let wt = MockWorktile::start();
+        let error_codes: HashMap<String, ErrorCategory> = vec![
+            ("100404".to_owned(), ErrorCategory::NotFound),
+            ("100403".to_owned(), ErrorCategory::Forbidden),
+            ("100429".to_owned(), ErrorCategory::RateLimited),
+        ]
+        .into_iter()
+        .collect();
+        let client = wt.client().error_codes(error_codes).build().unwrap();
+
+        for (code, category) in &[
+            ("100404", ErrorCategory::NotFound),
+            ("100403", ErrorCategory::Forbidden),
+            ("100429", ErrorCategory::RateLimited),
+            ("100500", ErrorCategory::Unknown)
+        ] {
+            wt.fault("GET", "v1/agile/work_items", Fault::Code(code), 1);
+            let e = client.work_item("CD-7").await.unwrap_err();
+            let api_error = e.downcast_ref::<ApiError>().unwrap();
+            assert_eq!(api_error.code(), Some(*code));
+            assert_eq!(api_error.category(), *category, "code {}", code);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn short_lived_token_refreshes_at_half_lifetime() {
+        let wt = MockWorktile::start();
+        let client = wt.client().build().unwrap();
+        client.work_item("CD-7").await.unwrap();
+        let token = client.tokens.current().unwrap();
+        assert_eq!(token.expires_in - token.refresh_at(), chrono::Duration::hours(12));
+
+        //有效期不超过12小时时在有效期过半时刷新，刷新时间不会早于当前时间
+        wt.token_ttl(3600);
+        client.clear_cache().unwrap();
+        client.work_item("CD-7").await.unwrap();
+        let token = client.tokens.current().unwrap();
+        let ahead = token.expires_in - token.refresh_at();
+        assert!(ahead > chrono::Duration::minutes(29) && ahead <= chrono::Duration::minutes(30));
+        assert!(token.refresh_at() > chrono::Utc::now().naive_utc());
+    }
+
+    #[actix_rt::test]
+    async fn concurrent_requests_share_token_refresh() {
+        let wt = MockWorktile::start();
+        wt.add_work_item("CD-7", "task", "新建");
+        wt.fault("GET", "v1/auth/token", Fault::Delay(Duration::from_millis(200)), 1);
+        let client = wt.client().build().unwrap();
+
+        let results = futures::future::join_all((0..5).map(|_| client.work_item("CD-7"))).await;
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(wt.calls_to("GET", "v1/auth/token").len(), 1);
+    }
+}